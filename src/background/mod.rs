@@ -11,23 +11,51 @@ use bevy::{
         shader::{ShaderStage, ShaderStages},
     },
 };
+use serde::{Deserialize, Serialize};
 #[derive(Default)]
 pub struct SeaBackgroundPlugin;
 impl Plugin for SeaBackgroundPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        let resources = app.resources_mut();
-        let mut render_graph = resources.get_mut::<RenderGraph>().unwrap();
-        render_graph.add_sea_background_graph(resources);
+        {
+            let resources = app.resources_mut();
+            let mut render_graph = resources.get_mut::<RenderGraph>().unwrap();
+            render_graph.add_sea_background_graph(resources);
+        }
+        app.add_system(water_time_system.system());
     }
 }
 
+//The time of the shader wraps after this many seconds, before f32 gets too imprecise.
+//The shaders only use speeds repeating over it, it must match TIME_PERIOD in sea_bg.frag.
+const WATER_TIME_PERIOD: f64 = 14400.;
+
 const VERTEX_SHADER: &str = include_str!("sea_bg.vert");
 
 const FRAGMENT_SHADER: &str = include_str!("sea_bg.frag");
 
+const SHORE_VERTEX_SHADER: &str = include_str!("shore.vert");
+
+const SHORE_FRAGMENT_SHADER: &str = include_str!("shore.frag");
+
+//The mesh attribute giving the distance of a vertex to the coast, in tiles
+pub const SHORE_ATTRIBUTE: &str = "Vertex_Shore";
+
 pub const SEA_BACKGROUND_PIPELINE_HANDLE: HandleUntyped =
     HandleUntyped::weak_from_u64(PipelineDescriptor::TYPE_UUID, 0xe5081d9abddbfad6);
 
+pub const SHORE_PIPELINE_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(PipelineDescriptor::TYPE_UUID, 0x3c1f6a0e92d4b857);
+
+fn build_shore_pipeline(shaders: &mut Assets<Shader>) -> PipelineDescriptor {
+    PipelineDescriptor::default_config(ShaderStages {
+        vertex: shaders.add(Shader::from_glsl(ShaderStage::Vertex, SHORE_VERTEX_SHADER)),
+        fragment: Some(shaders.add(Shader::from_glsl(
+            ShaderStage::Fragment,
+            SHORE_FRAGMENT_SHADER,
+        ))),
+    })
+}
+
 fn build_sea_background_pipeline(shaders: &mut Assets<Shader>) -> PipelineDescriptor {
     PipelineDescriptor::default_config(ShaderStages {
         vertex: shaders.add(Shader::from_glsl(ShaderStage::Vertex, VERTEX_SHADER)),
@@ -41,8 +69,8 @@ pub trait SeaBackgroundRenderGraphBuilder {
 
 impl SeaBackgroundRenderGraphBuilder for RenderGraph {
     fn add_sea_background_graph(&mut self, resources: &Resources) -> &mut Self {
-        // Add an AssetRenderResourcesNode to our Render Graph. This will bind TileUv resources to our shader
-        self.add_system_node("sea_background", RenderResourcesNode::<TileUv>::new(true));
+        // Add an AssetRenderResourcesNode to our Render Graph. This will bind SeaWater resources to our shader
+        self.add_system_node("sea_background", RenderResourcesNode::<SeaWater>::new(true));
 
         // Add a Render Graph edge connecting our new "sea_background" node to the main pass node. This ensures "sea_background" runs before the main pass
         self.add_node_edge("sea_background", base::node::MAIN_PASS)
@@ -53,19 +81,67 @@ impl SeaBackgroundRenderGraphBuilder for RenderGraph {
             SEA_BACKGROUND_PIPELINE_HANDLE,
             build_sea_background_pipeline(&mut shaders),
         );
+        pipelines.set_untracked(SHORE_PIPELINE_HANDLE, build_shore_pipeline(&mut shaders));
         self
     }
 }
+//The colours of the water of a biome, in rgb between 0 and 1
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WaterPalette {
+    pub deep: (f32, f32, f32),
+    pub shallow: (f32, f32, f32),
+    //the top of the waves
+    pub crest: (f32, f32, f32),
+}
+impl Default for WaterPalette {
+    fn default() -> Self {
+        WaterPalette {
+            deep: (0.11, 0.34, 0.55),
+            shallow: (0.2, 0.58, 0.7),
+            crest: (0.78, 0.92, 0.95),
+        }
+    }
+}
+
+//The uniforms of the water shader
 #[derive(Debug, Default, RenderResources, TypeUuid, Reflect)]
 #[uuid = "66fb00ca-60e9-4852-bf71-d82048b706a2"]
-pub struct TileUv {
-    pub uv: Vec2,
+pub struct SeaWater {
+    //in seconds
+    pub time: f32,
+    //the side of a pixel of the water, in world pixels
     pub scale: f32,
+    pub deep: Color,
+    pub shallow: Color,
+    pub crest: Color,
+    //the light of the time of day
+    pub tint: Color,
+    //the shadow of the clouds, from 0 to 1
+    pub darkness: f32,
+}
+impl SeaWater {
+    pub fn new(palette: &WaterPalette, scale: f32) -> Self {
+        let color = |(r, g, b): (f32, f32, f32)| Color::rgb(r, g, b);
+        SeaWater {
+            time: 0.,
+            scale,
+            deep: color(palette.deep),
+            shallow: color(palette.shallow),
+            crest: color(palette.crest),
+            tint: Color::WHITE,
+            darkness: 0.,
+        }
+    }
+}
+
+fn water_time_system(time: Res<Time>, mut water_query: Query<&mut SeaWater>) {
+    let seconds = (time.seconds_since_startup() % WATER_TIME_PERIOD) as f32;
+    for mut water in water_query.iter_mut() {
+        water.time = seconds;
+    }
 }
 #[derive(Bundle)]
 pub(crate) struct BackgroundBundle {
-    /// The handle for a TextureAtlas which contains multiple textures.
-    pub texture_atlas: Handle<TextureAtlas>,
     /// A component that indicates how to draw a component.
     pub draw: Draw,
     /// A component that indicates if the component is visible.
@@ -81,7 +157,7 @@ pub(crate) struct BackgroundBundle {
     pub transform: Transform,
     /// The global transform location in a space for a component.
     pub global_transform: GlobalTransform,
-    pub tile_uv: TileUv,
+    pub water: SeaWater,
     pub flag: BgFlag,
 }
 pub struct BgFlag;
@@ -93,16 +169,45 @@ impl Default for BackgroundBundle {
                 SEA_BACKGROUND_PIPELINE_HANDLE.typed(),
             )]),
             transform: Transform::from_rotation(Quat::from_rotation_x(std::f32::consts::PI / 2.)),
-            texture_atlas: Default::default(),
             draw: Default::default(),
             visible: Default::default(),
             main_pass: Default::default(),
             global_transform: Default::default(),
-            tile_uv: TileUv {
-                uv: Default::default(),
-                scale: 1.,
-            },
+            water: SeaWater::new(&WaterPalette::default(), 1.),
             flag: BgFlag,
         }
     }
 }
+
+//The shallow water and foam around an island, a child of the island
+#[derive(Bundle)]
+pub(crate) struct ShoreBundle {
+    pub draw: Draw,
+    pub visible: Visible,
+    pub render_pipelines: RenderPipelines,
+    pub main_pass: MainPass,
+    pub mesh: Handle<Mesh>,
+    pub transform: Transform,
+    pub global_transform: GlobalTransform,
+    pub water: SeaWater,
+}
+impl Default for ShoreBundle {
+    fn default() -> Self {
+        Self {
+            mesh: Default::default(),
+            render_pipelines: RenderPipelines::from_pipelines(vec![RenderPipeline::new(
+                SHORE_PIPELINE_HANDLE.typed(),
+            )]),
+            //under the island, over the sea
+            transform: Transform::from_translation(Vec3::new(0., 0., -1.)),
+            draw: Default::default(),
+            visible: Visible {
+                is_transparent: true,
+                ..Default::default()
+            },
+            main_pass: Default::default(),
+            global_transform: Default::default(),
+            water: SeaWater::new(&WaterPalette::default(), 1.),
+        }
+    }
+}
@@ -1,17 +1,87 @@
 #version 450
 layout(location = 0) in vec4 world_position;
 layout(location = 0) out vec4 o_Target;
-layout(set=2, binding = 0) uniform TileUv_uv {
-    vec2 uv;
+layout(set = 2, binding = 0) uniform SeaWater_time {
+    float time;
 };
-layout(set=2, binding = 2) uniform TileUv_scale {
+layout(set = 2, binding = 1) uniform SeaWater_scale {
     float scale;
 };
-layout(set = 3, binding = 0) uniform texture2D TextureAtlas_texture;
-layout(set = 3, binding = 1) uniform sampler TextureAtlas_texture_sampler;
+layout(set = 2, binding = 2) uniform SeaWater_deep {
+    vec4 deep;
+};
+layout(set = 2, binding = 3) uniform SeaWater_shallow {
+    vec4 shallow;
+};
+layout(set = 2, binding = 4) uniform SeaWater_crest {
+    vec4 crest;
+};
+layout(set = 2, binding = 5) uniform SeaWater_tint {
+    vec4 tint;
+};
+layout(set = 2, binding = 6) uniform SeaWater_darkness {
+    float darkness;
+};
+
+// The clouds darken the water and wash out its colours
+vec3 overcast(vec3 color) {
+    float grey = dot(color, vec3(0.3, 0.59, 0.11));
+    return mix(color, vec3(grey), darkness * 0.6) * (1. - darkness * 0.6);
+}
+
+// Everything is computed from the world position, so the water tiles seamlessly
+// whatever the position of the plane following the camera.
+
+// The time wraps after TIME_PERIOD seconds, which must match WATER_TIME_PERIOD.
+// The noise repeats every NOISE_PERIOD cells, and every layer scrolls a whole number of periods
+// in that time, so the water doesn't jump when the time wraps.
+const float TIME_PERIOD = 14400.;
+const float NOISE_PERIOD = 256.;
+const float SPEED_UNIT = NOISE_PERIOD / TIME_PERIOD;
+
+float hash(vec2 p) {
+    p = fract(p * vec2(123.34, 456.21));
+    p += dot(p, p + 45.32);
+    return fract(p.x * p.y);
+}
+
+// Smooth value noise between 0 and 1, repeating every NOISE_PERIOD cells
+float value_noise(vec2 p) {
+    vec2 i = floor(p);
+    vec2 f = fract(p);
+    vec2 u = f * f * (3. - 2. * f);
+    vec2 i0 = mod(i, NOISE_PERIOD);
+    vec2 i1 = mod(i + 1., NOISE_PERIOD);
+    return mix(
+        mix(hash(i0), hash(vec2(i1.x, i0.y)), u.x),
+        mix(hash(vec2(i0.x, i1.y)), hash(i1), u.x),
+        u.y);
+}
+
+// The height of the waves, three layers scrolling in different directions
+float waves(vec2 p) {
+    return 0.5 * value_noise(p / 24. + time * vec2(7., 3.) * SPEED_UNIT)
+        + 0.3 * value_noise(p / 11. + time * vec2(-6., 9.) * SPEED_UNIT)
+        + 0.2 * value_noise(p / 5. + time * vec2(12., -10.) * SPEED_UNIT);
+}
+
 void main() {
-    vec2 screen_offset = mod(world_position.xy/scale , 64.) / vec2(192., 64.);
-    o_Target = texture(
-        sampler2D(TextureAtlas_texture, TextureAtlas_texture_sampler),
-        uv+screen_offset);
-}
\ No newline at end of file
+    // the pattern is made of square pixels, like the sprites
+    vec2 p = floor(world_position.xy / scale);
+
+    // the slope of the waves lights them like a normal map
+    float height = waves(p);
+    vec2 slope = vec2(waves(p + vec2(1., 0.)) - height, waves(p + vec2(0., 1.)) - height);
+    float light = clamp(dot(slope, normalize(vec2(-1., 1.))) * 12., 0., 1.);
+
+    // a slow, large noise keeps the open sea from looking flat. It isn't the depth:
+    // the shallows along the coasts are drawn by the shore band over this plane.
+    float patches = value_noise(p / 160. + time * SPEED_UNIT);
+    vec3 color = mix(deep.rgb, shallow.rgb, smoothstep(0.3, 0.8, patches));
+    color = mix(color, shallow.rgb, height * 0.3);
+
+    // the crests are flat colour bands, to keep a pixel art look
+    float crest_amount = step(0.78, height + light * 0.2);
+    color = mix(color, crest.rgb, crest_amount * 0.8);
+    o_Target = vec4(overcast(color) * tint.rgb, 1.);
+}
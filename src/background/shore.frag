@@ -0,0 +1,59 @@
+#version 450
+layout(location = 0) in vec4 world_position;
+layout(location = 1) in float shore;
+layout(location = 0) out vec4 o_Target;
+layout(set = 2, binding = 0) uniform SeaWater_time {
+    float time;
+};
+layout(set = 2, binding = 1) uniform SeaWater_scale {
+    float scale;
+};
+layout(set = 2, binding = 3) uniform SeaWater_shallow {
+    vec4 shallow;
+};
+layout(set = 2, binding = 4) uniform SeaWater_crest {
+    vec4 crest;
+};
+layout(set = 2, binding = 5) uniform SeaWater_tint {
+    vec4 tint;
+};
+layout(set = 2, binding = 6) uniform SeaWater_darkness {
+    float darkness;
+};
+
+// The clouds darken the water and wash out its colours
+vec3 overcast(vec3 color) {
+    float grey = dot(color, vec3(0.3, 0.59, 0.11));
+    return mix(color, vec3(grey), darkness * 0.6) * (1. - darkness * 0.6);
+}
+
+// must match SHORE_WIDTH in the world generation
+const float SHORE_WIDTH = 3.;
+
+float hash(vec2 p) {
+    p = fract(p * vec2(123.34, 456.21));
+    p += dot(p, p + 45.32);
+    return fract(p.x * p.y);
+}
+
+void main() {
+    // the same square pixels as the sea
+    vec2 p = floor(world_position.xy / scale);
+    // 0 on the coast, 1 at the outer edge of the band
+    float band = clamp(shore / SHORE_WIDTH, 0., 1.);
+
+    // the shallow water fades into the sea
+    float shallow_alpha = (1. - band) * 0.6;
+
+    // the foam cycles 0.35 and 0.27 times a second, whole numbers of times over the time period
+    // of the water, so it doesn't jump when the time wraps
+    // lines of foam roll toward the coast, a bit broken up so they don't look drawn
+    float phase = fract(band * 2. + time * 0.35 + hash(floor(p / 6.)) * 0.15);
+    float rolling = step(0.85, phase) * (1. - band);
+    // the coast itself is lapped by a foam that swells and ebbs
+    float lapping = step(band, 0.12 + 0.08 * sin((time * 0.27 + hash(floor(p / 10.))) * 6.2831853));
+    float foam = max(rolling, lapping);
+
+    vec3 color = mix(shallow.rgb, crest.rgb, foam);
+    o_Target = vec4(overcast(color) * tint.rgb, max(shallow_alpha, foam * 0.9));
+}
@@ -0,0 +1,16 @@
+#version 450
+layout(location = 0) in vec3 Vertex_Position;
+layout(location = 1) in float Vertex_Shore;
+layout(location = 0) out vec4 world_position;
+layout(location = 1) out float shore;
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 ViewProj;
+};
+layout(set = 1, binding = 0) uniform Transform {
+    mat4 Model;
+};
+void main() {
+    gl_Position = ViewProj * Model * vec4(Vertex_Position, 1.0);
+    world_position = Model * vec4(Vertex_Position, 1.0);
+    shore = Vertex_Shore;
+}
@@ -0,0 +1,274 @@
+use std::{
+    collections::{HashMap, HashSet},
+    f32::consts::PI,
+    hash::Hasher,
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use seahash::SeaHasher;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    background::SeaWater,
+    loading::{GameState, SavePath},
+    util::SeededHasher,
+};
+
+pub struct ClockPlugin;
+impl Plugin for ClockPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<GameClock>()
+            .on_state_enter(GameState::STAGE, GameState::Sea, load_clock_system.system())
+            .add_system(tint_water_system.system())
+            .add_system(tint_sprites_system.system())
+            .add_system(tint_materials_system.system());
+        for state in [GameState::Sea, GameState::Land].iter() {
+            app.on_state_update(GameState::STAGE, state.clone(), clock_system.system())
+                .on_state_update(GameState::STAGE, state.clone(), autosave_system.system())
+                .on_state_exit(GameState::STAGE, state.clone(), save_clock_system.system());
+        }
+    }
+}
+
+const CLOCK_FILE: &str = "clock.ron";
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
+//the real seconds an in-game day lasts
+const DAY_LENGTH: f32 = 20. * 60.;
+//the hour of a new game
+const START_HOUR: f32 = 8.;
+//the light at midnight, the day being white. The lighting does the darkness, this is the colour.
+const NIGHT_TINT: (f32, f32, f32) = (0.6, 0.65, 0.9);
+//the light at sunrise and sunset
+const DUSK_TINT: (f32, f32, f32) = (1., 0.7, 0.5);
+//how far the ships see at night, relative to the day
+const NIGHT_VISIBILITY: f32 = 0.5;
+
+//The in-game time, going on at sea and on land and saved with the game
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GameClock {
+    //the clock of another world is discarded
+    #[serde(default)]
+    seed: u64,
+    //from 1
+    pub day: u32,
+    //from 0 to 24
+    pub hour: f32,
+}
+impl Default for GameClock {
+    fn default() -> Self {
+        GameClock {
+            seed: 0,
+            day: 1,
+            hour: START_HOUR,
+        }
+    }
+}
+impl GameClock {
+    pub fn advance(&mut self, seconds: f32) {
+        self.hour += seconds * 24. / DAY_LENGTH;
+        while self.hour >= 24. {
+            self.hour -= 24.;
+            self.day += 1;
+        }
+    }
+
+    //From 0 at midnight to 1 at noon
+    pub fn daylight(&self) -> f32 {
+        0.5 - 0.5 * f32::cos(self.hour / 24. * 2. * PI)
+    }
+
+    pub fn is_night(&self) -> bool {
+        self.daylight() < 0.25
+    }
+
+    //Whether the hour is between `start` and `end`, which can go over midnight like (20, 6)
+    pub fn in_hours(&self, (start, end): (f32, f32)) -> bool {
+        if start <= end {
+            self.hour >= start && self.hour < end
+        } else {
+            self.hour >= start || self.hour < end
+        }
+    }
+
+    //The share of the day sight distance, for the AI
+    pub fn visibility(&self) -> f32 {
+        NIGHT_VISIBILITY + (1. - NIGHT_VISIBILITY) * self.daylight()
+    }
+
+    //The colour the world is multiplied by
+    pub fn tint(&self) -> Color {
+        let daylight = self.daylight().powf(0.5);
+        //the dusk is the strongest around 6 and 18
+        let dusk = f32::exp(-(self.hour - 6.).powi(2)) + f32::exp(-(self.hour - 18.).powi(2));
+        let mix = |night: f32, dusk_value: f32| {
+            let value = night + (1. - night) * daylight;
+            value + (dusk_value - value) * dusk * 0.6
+        };
+        Color::rgb(
+            mix(NIGHT_TINT.0, DUSK_TINT.0),
+            mix(NIGHT_TINT.1, DUSK_TINT.1),
+            mix(NIGHT_TINT.2, DUSK_TINT.2),
+        )
+    }
+}
+
+//Sprites and materials with this component follow the light of the clock.
+//It holds their own colour, the tint being applied over it.
+pub struct Tinted(pub Color);
+
+fn tinted(base: Color, tint: Color) -> Color {
+    Color::rgba(
+        base.r() * tint.r(),
+        base.g() * tint.g(),
+        base.b() * tint.b(),
+        base.a(),
+    )
+}
+
+fn clock_seed(hasher: &SeededHasher) -> u64 {
+    let mut hasher: SeaHasher = hasher.get_hasher();
+    hasher.write(b"game_clock");
+    hasher.finish()
+}
+
+//The seed is only known once the menu is left, so the clock is loaded when the sea is entered.
+//The clock of the current world keeps going from one visit of the sea to the next.
+fn load_clock_system(
+    mut clock: ResMut<GameClock>,
+    save_path: Res<SavePath>,
+    hasher: Res<SeededHasher>,
+) {
+    let seed = clock_seed(&hasher);
+    if clock.seed == seed {
+        return;
+    }
+    *clock = save_path
+        .load::<GameClock>(CLOCK_FILE)
+        .filter(|clock| clock.seed == seed)
+        .unwrap_or(GameClock {
+            seed,
+            ..Default::default()
+        });
+}
+
+fn save_clock_system(save_path: Res<SavePath>, clock: Res<GameClock>) {
+    if let Err(e) = save_path.save(CLOCK_FILE, &*clock) {
+        warn!("could not save the clock: {}", e);
+    }
+}
+
+fn autosave_system(
+    time: Res<Time>,
+    mut timer: Local<Option<Timer>>,
+    save_path: Res<SavePath>,
+    clock: Res<GameClock>,
+) {
+    if timer.is_none() {
+        *timer = Some(Timer::new(AUTOSAVE_INTERVAL, true));
+    }
+    let timer = timer.as_mut().unwrap();
+    timer.tick(time.delta_seconds());
+    if timer.just_finished() {
+        save_clock_system(save_path, clock);
+    }
+}
+
+fn clock_system(time: Res<Time>, mut clock: ResMut<GameClock>) {
+    clock.advance(time.delta_seconds());
+}
+
+fn tint_water_system(clock: Res<GameClock>, mut water_query: Query<&mut SeaWater>) {
+    let tint = clock.tint();
+    for mut water in water_query.iter_mut() {
+        water.tint = tint;
+    }
+}
+
+fn tint_sprites_system(
+    clock: Res<GameClock>,
+    mut sprite_query: Query<(&Tinted, &mut TextureAtlasSprite)>,
+) {
+    let tint = clock.tint();
+    for (Tinted(base), mut sprite) in sprite_query.iter_mut() {
+        sprite.color = tinted(*base, tint);
+    }
+}
+
+//The tinted copies of the materials. The materials are shared, even with untinted entities, so the
+//tinted entities are moved to copies instead, one per material and colour.
+#[derive(Default)]
+struct TintedMaterials {
+    //the tint last written to the copies
+    tint: Option<Color>,
+    //from the material and the colour of the entity to the colour of the copy and the copy
+    copies: HashMap<(Handle<ColorMaterial>, [u32; 4]), (Color, Handle<ColorMaterial>)>,
+    copied: HashSet<Handle<ColorMaterial>>,
+}
+
+//The tint changes a bit every frame, the copies are only written when it changed this much
+const MATERIAL_TINT_STEP: f32 = 1. / 255.;
+
+fn tint_materials_system(
+    clock: Res<GameClock>,
+    mut tinted_materials: Local<TintedMaterials>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut material_query: Query<(&Tinted, &mut Handle<ColorMaterial>)>,
+) {
+    let tinted_materials = &mut *tinted_materials;
+    let tint = clock.tint();
+    let changed = tinted_materials.tint.map_or(true, |last| {
+        (last.r() - tint.r()).abs() >= MATERIAL_TINT_STEP
+            || (last.g() - tint.g()).abs() >= MATERIAL_TINT_STEP
+            || (last.b() - tint.b()).abs() >= MATERIAL_TINT_STEP
+    });
+    if changed {
+        tinted_materials.tint = Some(tint);
+        for (base, copy) in tinted_materials.copies.values() {
+            if let Some(material) = materials.get_mut(copy) {
+                material.color = tinted(*base, tint);
+            }
+        }
+    }
+    let tint = tinted_materials.tint.unwrap();
+
+    for (Tinted(color), mut handle) in material_query.iter_mut() {
+        if tinted_materials.copied.contains(&*handle) {
+            continue;
+        }
+        let key = (
+            (*handle).clone(),
+            [
+                color.r().to_bits(),
+                color.g().to_bits(),
+                color.b().to_bits(),
+                color.a().to_bits(),
+            ],
+        );
+        let copy = match tinted_materials.copies.get(&key) {
+            Some((_, copy)) => copy.clone(),
+            None => {
+                let (base, texture) = match materials.get(&*handle) {
+                    Some(material) => (
+                        Color::rgba(
+                            material.color.r() * color.r(),
+                            material.color.g() * color.g(),
+                            material.color.b() * color.b(),
+                            material.color.a() * color.a(),
+                        ),
+                        material.texture.clone(),
+                    ),
+                    None => continue,
+                };
+                let copy = materials.add(ColorMaterial {
+                    color: tinted(base, tint),
+                    texture,
+                });
+                tinted_materials.copied.insert(copy.clone());
+                tinted_materials.copies.insert(key, (base, copy.clone()));
+                copy
+            }
+        };
+        *handle = copy;
+    }
+}
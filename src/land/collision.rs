@@ -0,0 +1,147 @@
+use std::{collections::HashMap, sync::Arc};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    loading::GameState,
+    sea::{
+        map::{Islands, TileKind},
+        player::PlayerPositionUpdate,
+        worldgen::Tile,
+        TILE_SIZE,
+    },
+};
+
+use super::LAND_SCALING;
+
+pub struct LandCollisionPlugin;
+impl Plugin for LandCollisionPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(read_terrain_config())
+            .init_resource::<LandTerrain>()
+            .on_state_enter(
+                GameState::STAGE,
+                GameState::Land,
+                load_terrain_system.system(),
+            );
+    }
+}
+
+const TILE: f32 = TILE_SIZE as f32 * LAND_SCALING;
+//how far from a blocking tile a sliding box stops
+const SKIN: f32 = 0.01;
+
+//Where something can walk, and how hard it is. Each kind of tile has a cost, or None when it is
+//impassable. The kinds missing are impassable too.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TerrainProfile {
+    pub costs: HashMap<TileKind, Option<u32>>,
+}
+impl TerrainProfile {
+    //The cost of walking over a kind of tile, None when it is blocking
+    pub fn cost(&self, kind: TileKind) -> Option<u32> {
+        self.costs
+            .get(&kind)
+            .copied()
+            .flatten()
+            .filter(|cost| *cost > 0)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TerrainConfig {
+    //the land player, the mobs have theirs in config/mobs.ron
+    pub player: TerrainProfile,
+}
+
+fn read_terrain_config() -> TerrainConfig {
+    let terrain_config_string =
+        std::fs::read_to_string("config/terrain.ron").expect("terrain config file not found");
+    ron::from_str(&terrain_config_string).expect("syntax error on terrain config file")
+}
+
+//The tiles of the island the player landed on
+pub struct LandTerrain(pub Arc<Vec<Vec<Tile>>>);
+impl Default for LandTerrain {
+    fn default() -> Self {
+        LandTerrain(Arc::new(Vec::new()))
+    }
+}
+
+fn load_terrain_system(
+    mut terrain: ResMut<LandTerrain>,
+    sea_player_pos: Res<PlayerPositionUpdate>,
+    islands: Res<Islands>,
+) {
+    let island = &islands.0[sea_player_pos.island_id.unwrap() as usize];
+    terrain.0 = island.tiles.clone();
+}
+
+//The tile coordinates of a position on land
+pub fn tile_coord(position: Vec2) -> (i32, i32) {
+    let tile = (position / TILE).floor();
+    (tile.x as i32, tile.y as i32)
+}
+
+//The tile at some coordinates, None out of the island
+pub fn tile_at(tiles: &[Vec<Tile>], (x, y): (i32, i32)) -> Option<&Tile> {
+    if x < 0 || y < 0 {
+        return None;
+    }
+    tiles.get(x as usize)?.get(y as usize)
+}
+
+//The cost of walking over the tile at some coordinates, None when it is blocking.
+//Around the island is the sea, which is blocking too.
+pub fn terrain_cost(
+    tiles: &[Vec<Tile>],
+    profile: &TerrainProfile,
+    coord: (i32, i32),
+) -> Option<u32> {
+    profile.cost(tile_at(tiles, coord)?.kind)
+}
+
+//Whether a box of half size `half_size` centered on `position` only covers walkable tiles
+fn is_free(tiles: &[Vec<Tile>], profile: &TerrainProfile, position: Vec2, half_size: Vec2) -> bool {
+    let (min_x, min_y) = tile_coord(position - half_size);
+    let (max_x, max_y) = tile_coord(position + half_size);
+    (min_x..=max_x).all(|x| (min_y..=max_y).all(|y| terrain_cost(tiles, profile, (x, y)).is_some()))
+}
+
+//Moves a box of half size `half_size` by `movement`, stopping it against the blocking tiles.
+//Each axis is resolved on its own, so the box slides along the walls instead of sticking to them.
+//A box already on blocking tiles moves freely until it is out, so it can't get stuck.
+pub fn slide(
+    tiles: &[Vec<Tile>],
+    profile: &TerrainProfile,
+    position: Vec2,
+    movement: Vec2,
+    half_size: Vec2,
+) -> Vec2 {
+    if !is_free(tiles, profile, position, half_size) {
+        return position + movement;
+    }
+    let mut position = position;
+    for axis in 0..2 {
+        let delta = movement[axis];
+        if delta == 0. {
+            continue;
+        }
+        let mut moved = position;
+        moved[axis] += delta;
+        if is_free(tiles, profile, moved, half_size) {
+            position = moved;
+            continue;
+        }
+        //stops against the edge of the tile the box entered
+        let edge = position[axis] + half_size[axis] * delta.signum();
+        let tile_edge = if delta > 0. {
+            ((edge / TILE).floor() + 1.) * TILE - SKIN
+        } else {
+            (edge / TILE).floor() * TILE + SKIN
+        };
+        position[axis] = tile_edge - half_size[axis] * delta.signum();
+    }
+    position
+}
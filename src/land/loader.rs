@@ -11,7 +11,6 @@ pub(crate) struct LandHandles {
     pub player: Handle<TextureAtlas>,
     pub tiles: Handle<TextureAtlas>,
     pub island_material: Handle<ColorMaterial>,
-    pub sea_sheet: Handle<TextureAtlas>,
 }
 
 pub struct LandLoaderPlugin;
@@ -60,12 +59,6 @@ fn setup(
     let texture_atlas_handle = texture_atlases.add(islands_atlas);
     handles.tiles = texture_atlas_handle;
 
-    let texture_handle_sea_spritesheet = asset_server.load("sprites/sea/seaTileSheet.png");
-
-    let sea_atlas =
-        TextureAtlas::from_grid(texture_handle_sea_spritesheet, Vec2::new(64., 64.), 3, 1);
-    handles.sea_sheet = texture_atlases.add(sea_atlas);
-
     *mobs_config = MobsConfig(Arc::new(
         read_mob_config()
             .drain(..)
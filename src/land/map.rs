@@ -1,9 +1,9 @@
-use std::time::Duration;
-
 use crate::{
-    background::{BackgroundBundle, TileUv},
+    background::{BackgroundBundle, BgFlag, SeaWater, ShoreBundle},
+    clock::Tinted,
+    lighting::LightSource,
     loading::GameState,
-    sea::{map::Islands, player::PlayerPositionUpdate},
+    sea::{map::Islands, player::PlayerPositionUpdate, worldgen::GenRessources},
     util::SeededHasher,
 };
 use bevy::{prelude::*, render::camera::Camera};
@@ -11,6 +11,7 @@ use bevy::{prelude::*, render::camera::Camera};
 use super::{
     loader::{LandHandles, MobsConfig, UnloadLandFlag},
     mobs::generate_mobs,
+    pathfinding::PathfindingRegistry,
     LAND_SCALING,
 };
 pub struct LandMapPlugin;
@@ -42,6 +43,7 @@ fn load_island(
     mut islands: ResMut<Islands>,
     handles: Res<LandHandles>,
     mut meshes: ResMut<Assets<Mesh>>,
+    gen_ressources: Local<GenRessources>,
 ) {
     let island = &mut islands.0[sea_player_pos.island_id.unwrap() as usize];
     commands
@@ -55,7 +57,23 @@ fn load_island(
             material: handles.island_material.clone(),
             ..Default::default()
         })
-        .with(UnloadLandFlag);
+        .with(UnloadLandFlag)
+        .with(Tinted(Color::WHITE))
+        .with_children(|parent| {
+            parent.spawn(ShoreBundle {
+                mesh: island.shore_mesh.clone(),
+                water: SeaWater::new(&gen_ressources.biome.water, 2. * LAND_SCALING),
+                ..Default::default()
+            });
+            for (kind, position) in island.lights.iter() {
+                parent
+                    .spawn((
+                        Transform::from_translation(position.extend(0.)),
+                        GlobalTransform::default(),
+                    ))
+                    .with(LightSource(*kind));
+            }
+        });
     //initializing the sea animation
     let mut transform = Transform::from_rotation(Quat::from_rotation_x(std::f32::consts::PI / 2.));
     transform.translation.z = 0.;
@@ -63,38 +81,21 @@ fn load_island(
         .spawn(BackgroundBundle {
             mesh: meshes.add(Mesh::from(shape::Plane { size: 10000.0 })),
             transform,
-            texture_atlas: handles.sea_sheet.clone(),
-            tile_uv: TileUv {
-                uv: Vec2::new(0.0, 0.0),
-                scale: 2. * LAND_SCALING,
-            },
+            water: SeaWater::new(&gen_ressources.biome.water, 2. * LAND_SCALING),
             ..Default::default()
         })
         .with(UnloadLandFlag);
 }
 
 fn move_anim_bg(
-    mut bg_query: Query<(&mut TileUv, &mut Transform)>,
+    mut bg_query: Query<&mut Transform, With<BgFlag>>,
     camera_query: Query<&Transform, With<Camera>>,
-    time: Res<Time>,
-    mut timer: Local<Option<Timer>>,
 ) {
-    if timer.is_none() {
-        *timer = Some(Timer::new(Duration::from_millis(500), true));
-    }
-    let timer = timer.as_mut().unwrap();
-    for (mut bg, mut bg_transform) in bg_query.iter_mut() {
+    for mut bg_transform in bg_query.iter_mut() {
         for camera_transform in camera_query.iter() {
             bg_transform.translation.x = camera_transform.translation.x;
             bg_transform.translation.y = camera_transform.translation.y;
         }
-        timer.tick(time.delta_seconds());
-        if timer.finished() {
-            bg.uv += Vec2::new(1. / 3., 0.);
-            if bg.uv.x >= 0.99 {
-                bg.uv = Vec2::new(0., 0.)
-            }
-        }
     }
 }
 
@@ -103,9 +104,15 @@ fn generate_islands_features(
     mut id: Local<usize>,
     hasher: Res<SeededHasher>,
     mobs_config: Res<MobsConfig>,
+    pathfindings: Res<PathfindingRegistry>,
 ) {
     for i in *id..islands.0.len() {
-        generate_mobs(&mobs_config, &mut islands.0[i], hasher.get_hasher())
+        generate_mobs(
+            &mobs_config,
+            &pathfindings,
+            &mut islands.0[i],
+            hasher.get_hasher(),
+        )
     }
     *id = islands.0.len();
 }
@@ -1,6 +1,8 @@
 use std::{
+    collections::HashMap,
     fmt::Debug,
     hash::{Hash, Hasher},
+    time::Duration,
 };
 
 use bevy::prelude::*;
@@ -8,6 +10,7 @@ use seahash::SeaHasher;
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    clock::{GameClock, Tinted},
     loading::GameState,
     sea::{
         map::{Islands, TileKind},
@@ -18,9 +21,12 @@ use crate::{
 };
 
 use super::{
+    collision::TerrainProfile,
     loader::MobsConfig,
-    pathfinding::get_pathfinding,
-    pathfinding::{Pathfinder, PathfindingType},
+    pathfinding::{
+        AStar, FlowField, LineOfSight, NoPathfinding, Pathfinder, PathfindingAppBuilder,
+        PathfindingConfig, PathfindingContext, PathfindingRegistry, SharedDistanceField,
+    },
     player::Player,
     player::PlayerMovedEvent,
     LAND_SCALING,
@@ -29,19 +35,29 @@ use super::{
 pub struct LandMobsPlugin;
 impl Plugin for LandMobsPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.on_state_update(GameState::STAGE, GameState::Land, mob_movement.system())
+        app.register_pathfinding("None", NoPathfinding::build)
+            .register_pathfinding("LineOfSight", LineOfSight::build)
+            .register_pathfinding("AStar", AStar::build)
+            .register_pathfinding("FlowField", FlowField::build)
+            .on_state_update(GameState::STAGE, GameState::Land, mob_movement.system())
+            .on_state_update(GameState::STAGE, GameState::Land, mob_hours_system.system())
             .on_state_enter(GameState::STAGE, GameState::Land, load_mobs.system())
             .init_resource::<MobsConfig>()
             .on_state_exit(GameState::STAGE, GameState::Land, unload_mobs.system());
     }
 }
 
+//how often the hidden mobs are checked, in real time
+const MOB_HOURS_INTERVAL: Duration = Duration::from_secs(1);
+
 #[derive(Default, Clone)]
 pub struct Mob {
     pub kind: String,
     pub material: Handle<ColorMaterial>,
     pub speed: f32,
     pub pathfinder: Option<Pathfinder>,
+    //the hours the mob is out, it stays hidden the rest of the day
+    pub active_hours: Option<(f32, f32)>,
 }
 
 impl Debug for Mob {
@@ -61,6 +77,9 @@ pub struct SpawnConfig {
     pub biome: String,
     pub tile_kind: TileKind,
     pub rate: f32,
+    //the start and end hours the mob is out, like (20., 6.) for a night mob. Always out if missing.
+    #[serde(default)]
+    pub active_hours: Option<(f32, f32)>,
 }
 #[derive(Serialize, Deserialize)]
 pub enum ColliderType {
@@ -74,7 +93,9 @@ pub struct MobConfig {
     pub speed: f32,
     pub size: f32,
     collider: ColliderType,
-    pub pathfinding: PathfindingType,
+    pub pathfinding: PathfindingConfig,
+    //where the mob can walk, for every kind of pathfinding
+    pub terrain: TerrainProfile,
     pub spawn: Vec<SpawnConfig>,
 }
 
@@ -119,26 +140,81 @@ fn unload_mobs(
     }
 }
 
+fn spawn_mob(commands: &mut Commands, mob: Mob, transform: Transform) {
+    commands //mob
+        .spawn(SpriteBundle {
+            material: mob.material.clone(),
+            transform,
+            ..Default::default()
+        })
+        .with(Tinted(Color::WHITE))
+        .with(mob);
+}
+
+fn is_active(mob: &Mob, clock: &GameClock) -> bool {
+    mob.active_hours
+        .map_or(true, |active_hours| clock.in_hours(active_hours))
+}
+
 fn load_mobs(
     commands: &mut Commands,
     sea_player_pos: Res<PlayerPositionUpdate>,
+    clock: Res<GameClock>,
     mut islands: ResMut<Islands>,
 ) {
     let island = &mut islands.0[sea_player_pos.island_id.unwrap() as usize];
+    let (active, hidden) = island
+        .mobs
+        .drain(..)
+        .partition(|(mob, _): &(Mob, _)| is_active(mob, &clock));
+    island.mobs = hidden;
+    for (mob, transform) in active {
+        spawn_mob(commands, mob, transform);
+    }
+}
 
-    for (mob, transform) in island.mobs.drain(..) {
-        commands //mob
-            .spawn(SpriteBundle {
-                material: mob.material.clone(),
-                transform,
-                ..Default::default()
-            })
-            .with(mob);
+//The mobs come out and hide as the hours go by while the player is on the island
+fn mob_hours_system(
+    commands: &mut Commands,
+    time: Res<Time>,
+    mut timer: Local<Option<Timer>>,
+    sea_player_pos: Res<PlayerPositionUpdate>,
+    clock: Res<GameClock>,
+    mut islands: ResMut<Islands>,
+    query: Query<(Entity, &Mob, &Transform)>,
+) {
+    if timer.is_none() {
+        *timer = Some(Timer::new(MOB_HOURS_INTERVAL, true));
+    }
+    let timer = timer.as_mut().unwrap();
+    timer.tick(time.delta_seconds());
+    if !timer.just_finished() {
+        return;
+    }
+    let island = &mut islands.0[sea_player_pos.island_id.unwrap() as usize];
+    let (active, hidden) = island
+        .mobs
+        .drain(..)
+        .partition(|(mob, _): &(Mob, _)| is_active(mob, &clock));
+    island.mobs = hidden;
+    for (entity, mob, transform) in query.iter() {
+        if !is_active(mob, &clock) {
+            commands.despawn_recursive(entity);
+            island.mobs.push((mob.clone(), *transform));
+        }
+    }
+    for (mob, transform) in active {
+        spawn_mob(commands, mob, transform);
     }
 }
 
 //should use spawn tables at some point. Json config should be used for lots of things actually
-pub fn generate_mobs(mobs_config: &MobsConfig, island: &mut Island, mut hasher: SeaHasher) {
+pub fn generate_mobs(
+    mobs_config: &MobsConfig,
+    pathfindings: &PathfindingRegistry,
+    island: &mut Island,
+    mut hasher: SeaHasher,
+) {
     "generate_mobs".to_string().hash(&mut hasher); //to shuffle things a bit between different rng gen
                                                    //iterate over the tiles and their coordinates
     let mut island_hasher = hasher;
@@ -146,6 +222,8 @@ pub fn generate_mobs(mobs_config: &MobsConfig, island: &mut Island, mut hasher:
     island_hasher.write_i32(island.max_x);
     island_hasher.write_i32(island.min_y);
     island_hasher.write_i32(island.max_y);
+    //the mobs of a kind following a flow field share it on the island, as they walk on the same tiles
+    let mut distance_fields: HashMap<String, SharedDistanceField> = HashMap::new();
     for (coord, tile) in island
         .tiles
         .iter()
@@ -166,9 +244,15 @@ pub fn generate_mobs(mobs_config: &MobsConfig, island: &mut Island, mut hasher:
                 if tile_kind == spawn_config.tile_kind {
                     let number = (spawn_config.rate * MAX_SPAWN_RATE) as u64;
                     if hash >= current_number && hash < number + current_number {
-                        let pathfinder = Some(get_pathfinding(
-                            &island.tiles,
-                            mob_config.pathfinding.clone(),
+                        let distance_field =
+                            distance_fields.entry(mob_config.kind.clone()).or_default();
+                        let pathfinder = Some(pathfindings.build(
+                            &mob_config.pathfinding,
+                            &PathfindingContext {
+                                collision: &island.tiles,
+                                terrain: &mob_config.terrain,
+                                distance_field,
+                            },
                         ));
                         island.mobs.push((
                             Mob {
@@ -176,6 +260,7 @@ pub fn generate_mobs(mobs_config: &MobsConfig, island: &mut Island, mut hasher:
                                 speed: mob_config.speed,
                                 material: material.clone(),
                                 pathfinder,
+                                active_hours: spawn_config.active_hours,
                             },
                             Transform {
                                 translation: Vec3::new(
@@ -6,6 +6,7 @@ pub(crate) mod pathfinding;
 pub(crate) mod player;
 pub(crate) mod ui;
 use bevy::prelude::*;
+use collision::LandCollisionPlugin;
 use loader::LandLoaderPlugin;
 use map::LandMapPlugin;
 use player::LandPlayerPlugin;
@@ -19,6 +20,7 @@ impl Plugin for LandPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.add_plugin(LandLoaderPlugin)
             .add_plugin(LandPlayerPlugin)
+            .add_plugin(LandCollisionPlugin)
             .add_plugin(LandMapPlugin)
             .add_plugin(LandMobsPlugin)
             .add_plugin(LandUiPlugin);
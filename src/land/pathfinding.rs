@@ -1,8 +1,16 @@
-use std::sync::Arc;
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    sync::{Arc, RwLock},
+};
 
-use super::LAND_SCALING;
+use super::{
+    collision::{tile_at, tile_coord, TerrainProfile},
+    LAND_SCALING,
+};
 use crate::sea::{worldgen::Tile, TILE_SIZE};
-use bevy::math::Vec2;
+use bevy::prelude::*;
+use ron::Value;
 use serde::{Deserialize, Serialize};
 const TILE: f32 = TILE_SIZE as f32 * LAND_SCALING;
 
@@ -11,35 +19,116 @@ pub struct NoPathError;
 
 #[derive(Debug, Clone)]
 pub struct PathFinishedError;
-#[derive(Clone)]
-pub enum Pathfinder {
-    None(NoPathfinding),
-    LineOfSight(LineOfSight),
+
+//A way for the mobs to go to the player
+pub trait Pathfinding: Send + Sync {
+    //Looks for a way from the mob to the player, called when the player moves
+    fn find_path(&mut self, mob_pos: Vec2, player_pos: Vec2) -> Result<(), NoPathError>;
+    //The next position of the mob along the way, called every frame
+    fn step(&mut self, speed: f32, delta_time: f32) -> Result<Vec2, PathFinishedError>;
+    fn box_clone(&self) -> Box<dyn Pathfinding>;
+}
+
+//The pathfinding of a mob
+pub struct Pathfinder(Box<dyn Pathfinding>);
+impl Clone for Pathfinder {
+    fn clone(&self) -> Self {
+        Pathfinder(self.0.box_clone())
+    }
 }
 impl Pathfinder {
     pub fn find_path(&mut self, mob_pos: Vec2, player_pos: Vec2) -> Result<(), NoPathError> {
-        match self {
-            Pathfinder::None(p) => p.find_path(mob_pos, player_pos),
-            Pathfinder::LineOfSight(p) => p.find_path(mob_pos, player_pos),
-        }
+        self.0.find_path(mob_pos, player_pos)
     }
     pub fn step(&mut self, speed: f32, delta_time: f32) -> Result<Vec2, PathFinishedError> {
-        match self {
-            Pathfinder::None(p) => p.step(speed, delta_time),
-            Pathfinder::LineOfSight(p) => p.step(speed, delta_time),
-        }
+        self.0.step(speed, delta_time)
     }
 }
 
+//The pathfinding of a kind of mob in config/mobs.ron : the name it is registered with, and its parameters
 #[derive(Serialize, Deserialize, Debug, Clone)]
-pub enum PathfindingType {
-    LineOfSight(f32),
-    None,
+pub struct PathfindingConfig {
+    pub name: String,
+    #[serde(default = "no_params")]
+    pub params: Value,
 }
+fn no_params() -> Value {
+    Value::Unit
+}
+
+//What the pathfindings of the mobs of an island are built from
+pub struct PathfindingContext<'a> {
+    pub collision: &'a Arc<Vec<Vec<Tile>>>,
+    pub terrain: &'a TerrainProfile,
+    //shared by the mobs of the same kind on the island
+    pub distance_field: &'a SharedDistanceField,
+}
+
+//Builds a pathfinding from its parameters
+pub type PathfindingBuilder =
+    fn(&Value, &PathfindingContext) -> Result<Box<dyn Pathfinding>, ron::de::Error>;
+
+//The pathfindings the mobs can use, by name. Any plugin can add its own with `register_pathfinding`.
+#[derive(Default)]
+pub struct PathfindingRegistry(HashMap<String, PathfindingBuilder>);
+impl PathfindingRegistry {
+    pub fn register(&mut self, name: &str, builder: PathfindingBuilder) {
+        self.0.insert(name.to_string(), builder);
+    }
+
+    //Gets a pathfinder for the current island and with the choosen pathfinding
+    pub fn build(&self, config: &PathfindingConfig, context: &PathfindingContext) -> Pathfinder {
+        let builder = self.0.get(&config.name).unwrap_or_else(|| {
+            panic!(
+                "unknown pathfinding {} on mobs config file, the registered ones are {:?}",
+                config.name,
+                self.0.keys().collect::<Vec<_>>()
+            )
+        });
+        Pathfinder(builder(&config.params, context).unwrap_or_else(|e| {
+            panic!(
+                "syntax error on the {} pathfinding parameters on mobs config file: {}",
+                config.name, e
+            )
+        }))
+    }
+}
+
+pub trait PathfindingAppBuilder {
+    fn register_pathfinding(&mut self, name: &str, builder: PathfindingBuilder) -> &mut Self;
+}
+impl PathfindingAppBuilder for AppBuilder {
+    fn register_pathfinding(&mut self, name: &str, builder: PathfindingBuilder) -> &mut Self {
+        let resources = self.resources_mut();
+        if !resources.contains::<PathfindingRegistry>() {
+            resources.insert(PathfindingRegistry::default());
+        }
+        resources
+            .get_mut::<PathfindingRegistry>()
+            .unwrap()
+            .register(name, builder);
+        self
+    }
+}
+
+//the parameters of the pathfindings only looking for the player within some distance
+#[derive(Deserialize)]
+struct ViewDistance {
+    view_distance: f32,
+}
+
 #[derive(Clone)]
 pub struct NoPathfinding;
-
 impl NoPathfinding {
+    pub fn build(
+        _params: &Value,
+        _context: &PathfindingContext,
+    ) -> Result<Box<dyn Pathfinding>, ron::de::Error> {
+        Ok(Box::new(NoPathfinding))
+    }
+}
+
+impl Pathfinding for NoPathfinding {
     /// Empty pathfinding : the mob won't move
     fn find_path(&mut self, _mob_pos: Vec2, _player_pos: Vec2) -> Result<(), NoPathError> {
         Err(NoPathError)
@@ -48,10 +137,14 @@ impl NoPathfinding {
     fn step(&mut self, _speed: f32, _delta_time: f32) -> Result<Vec2, PathFinishedError> {
         Err(PathFinishedError)
     }
+
+    fn box_clone(&self) -> Box<dyn Pathfinding> {
+        Box::new(self.clone())
+    }
 }
 
 const SAMPLES_PER_TILE: f32 = 10.;
-#[derive(Default, Clone)]
+#[derive(Clone)]
 pub struct LineOfSight {
     /// line of sight pathfinding
     /// the mobs check the following :
@@ -67,26 +160,33 @@ pub struct LineOfSight {
     pub path_len: f32,
     pub transition: f32,
     pub collision: Arc<Vec<Vec<Tile>>>,
+    pub terrain: TerrainProfile,
 }
 impl LineOfSight {
+    pub fn build(
+        params: &Value,
+        context: &PathfindingContext,
+    ) -> Result<Box<dyn Pathfinding>, ron::de::Error> {
+        let ViewDistance { view_distance } = params.clone().into_rust()?;
+        Ok(Box::new(LineOfSight {
+            view_distance,
+            destination: Vec2::zero(),
+            origin: Vec2::zero(),
+            path_len: 0.,
+            transition: 0.,
+            collision: context.collision.clone(),
+            terrain: context.terrain.clone(),
+        }))
+    }
+}
+
+impl Pathfinding for LineOfSight {
     fn find_path(&mut self, mob_pos: Vec2, player_pos: Vec2) -> Result<(), NoPathError> {
         let path_len = (player_pos - mob_pos).length();
         if path_len > self.view_distance {
             return Err(NoPathError);
         }
-        let n_samples = path_len / TILE * SAMPLES_PER_TILE;
-        let step = (mob_pos - player_pos) / n_samples;
-        let clear = (0..n_samples as u32 + 1).into_iter().all(|i| {
-            let tile = ((mob_pos + i as f32 * step) / TILE).floor();
-            self.collision
-                .get(tile.x as usize)
-                .unwrap_or(&Vec::new())
-                .get(tile.y as usize)
-                .map(|t| get_tile_cost(t))
-                .unwrap_or(1)
-                > 0
-        });
-        if clear {
+        if is_line_clear(&self.collision, &self.terrain, mob_pos, player_pos) {
             self.destination = player_pos;
             self.origin = mob_pos;
             self.path_len = path_len;
@@ -105,28 +205,380 @@ impl LineOfSight {
             Err(PathFinishedError)
         }
     }
+
+    fn box_clone(&self) -> Box<dyn Pathfinding> {
+        Box::new(self.clone())
+    }
+}
+
+//The highest cost of the tiles on the segment between `from` and `to`, None when one is blocking
+fn line_max_cost(
+    tiles: &[Vec<Tile>],
+    terrain: &TerrainProfile,
+    from: Vec2,
+    to: Vec2,
+) -> Option<isize> {
+    let n_samples = (to - from).length() / TILE * SAMPLES_PER_TILE;
+    let step = (to - from) / n_samples.max(1.);
+    (0..n_samples as u32 + 1)
+        .into_iter()
+        .try_fold(0, |max_cost, i| {
+            let tile = tile_coord(from + i as f32 * step);
+            walkable_cost(tiles, terrain, tile).map(|cost| cost.max(max_cost))
+        })
+}
+
+//Whether every tile on the segment between `from` and `to` can be walked on
+fn is_line_clear(tiles: &[Vec<Tile>], terrain: &TerrainProfile, from: Vec2, to: Vec2) -> bool {
+    line_max_cost(tiles, terrain, from, to).is_some()
+}
+
+//the tiles explored by a search before giving up
+const MAX_SEARCHED_TILES: usize = 2000;
+//the costs of a straight and a diagonal move on a tile of cost 1
+const STRAIGHT_COST: isize = 10;
+const DIAGONAL_COST: isize = 14;
+const NEIGHBOURS: [(i32, i32); 8] = [
+    (1, 0),
+    (-1, 0),
+    (0, 1),
+    (0, -1),
+    (1, 1),
+    (1, -1),
+    (-1, 1),
+    (-1, -1),
+];
+
+//The cost of walking over the tile at some coordinates, None when it is blocking
+fn walkable_cost(tiles: &[Vec<Tile>], terrain: &TerrainProfile, tile: (i32, i32)) -> Option<isize> {
+    tile_at(tiles, tile)
+        .map(|t| get_tile_cost(t, terrain))
+        .filter(|cost| *cost > 0)
+}
+
+//The walkable tiles around a tile, and the cost of walking into them.
+//The diagonals don't cut the corners of the obstacles.
+fn neighbours(
+    tiles: &[Vec<Tile>],
+    terrain: &TerrainProfile,
+    (x, y): (i32, i32),
+) -> Vec<((i32, i32), isize)> {
+    NEIGHBOURS
+        .iter()
+        .filter_map(|(dx, dy)| {
+            let neighbour = (x + dx, y + dy);
+            let cost = walkable_cost(tiles, terrain, neighbour)?;
+            if *dx == 0 || *dy == 0 {
+                return Some((neighbour, cost * STRAIGHT_COST));
+            }
+            walkable_cost(tiles, terrain, (x + dx, y))?;
+            walkable_cost(tiles, terrain, (x, y + dy))?;
+            Some((neighbour, cost * DIAGONAL_COST))
+        })
+        .collect()
+}
+
+#[derive(Clone)]
+pub struct AStar {
+    /// A* pathfinding over the tiles of the island
+    /// When the player is within the view distance, the mob searches the cheapest path to them
+    /// according to the tile costs, then smooths it so it only turns around the obstacles.
+    /// While the player stays around the end of the path, the path is extended instead of searched again.
+    pub view_distance: f32,
+    //the smoothed path, from the mob to the player
+    pub path: Vec<Vec2>,
+    //the index of the point the mob walks to
+    pub next: usize,
+    pub position: Vec2,
+    //the tile the path leads to
+    pub goal: Option<(i32, i32)>,
+    pub collision: Arc<Vec<Vec<Tile>>>,
+    pub terrain: TerrainProfile,
+}
+impl AStar {
+    pub fn build(
+        params: &Value,
+        context: &PathfindingContext,
+    ) -> Result<Box<dyn Pathfinding>, ron::de::Error> {
+        let ViewDistance { view_distance } = params.clone().into_rust()?;
+        Ok(Box::new(AStar {
+            view_distance,
+            path: Vec::new(),
+            next: 0,
+            position: Vec2::zero(),
+            goal: None,
+            collision: context.collision.clone(),
+            terrain: context.terrain.clone(),
+        }))
+    }
+}
+
+impl Pathfinding for AStar {
+    fn find_path(&mut self, mob_pos: Vec2, player_pos: Vec2) -> Result<(), NoPathError> {
+        if (player_pos - mob_pos).length() > self.view_distance {
+            return Err(NoPathError);
+        }
+        let goal = tile_coord(player_pos);
+        let following = self.next < self.path.len();
+        if following && self.goal == Some(goal) {
+            return Ok(());
+        }
+        //the player moved to a tile next to the end of the path, which still leads to them
+        if let (Some(last_goal), Some(last)) = (self.goal, self.path.last().copied()) {
+            if following
+                && (goal.0 - last_goal.0).abs() <= 1
+                && (goal.1 - last_goal.1).abs() <= 1
+                && is_line_clear(&self.collision, &self.terrain, last, player_pos)
+            {
+                self.path.push(player_pos);
+                self.goal = Some(goal);
+                return Ok(());
+            }
+        }
+        let tiles = self.search(tile_coord(mob_pos), goal).ok_or(NoPathError)?;
+        //the ends are the mob and the player themselves, the centres of the tiles in between
+        let mut points = vec![mob_pos];
+        for (x, y) in tiles.iter().skip(1).take(tiles.len().saturating_sub(2)) {
+            points.push((Vec2::new(*x as f32, *y as f32) + Vec2::new(0.5, 0.5)) * TILE);
+        }
+        points.push(player_pos);
+        self.path = self.smooth(points);
+        self.next = 1;
+        self.position = mob_pos;
+        self.goal = Some(goal);
+        Ok(())
+    }
+
+    fn step(&mut self, speed: f32, delta_time: f32) -> Result<Vec2, PathFinishedError> {
+        if self.next >= self.path.len() {
+            return Err(PathFinishedError);
+        }
+        let mut distance = speed * delta_time * TILE;
+        while let Some(next) = self.path.get(self.next) {
+            let to_next = *next - self.position;
+            let length = to_next.length();
+            if length > distance {
+                self.position += to_next / length * distance;
+                break;
+            }
+            distance -= length;
+            self.position = *next;
+            self.next += 1;
+        }
+        Ok(self.position)
+    }
+
+    fn box_clone(&self) -> Box<dyn Pathfinding> {
+        Box::new(self.clone())
+    }
+}
+
+impl AStar {
+    //The tiles from `start` to `goal`, both included, None when the goal can't be reached
+    fn search(&self, start: (i32, i32), goal: (i32, i32)) -> Option<Vec<(i32, i32)>> {
+        walkable_cost(&self.collision, &self.terrain, goal)?;
+        //the octile distance, a move costing at least 1
+        let heuristic = |(x, y): (i32, i32)| {
+            let (dx, dy) = ((x - goal.0).abs() as isize, (y - goal.1).abs() as isize);
+            STRAIGHT_COST * dx.max(dy) + (DIAGONAL_COST - STRAIGHT_COST) * dx.min(dy)
+        };
+        let mut open = BinaryHeap::new();
+        let mut costs: HashMap<(i32, i32), isize> = HashMap::new();
+        let mut came_from: HashMap<(i32, i32), (i32, i32)> = HashMap::new();
+        open.push(Reverse((heuristic(start), start)));
+        costs.insert(start, 0);
+        let mut searched = 0;
+        while let Some(Reverse((_, tile))) = open.pop() {
+            if tile == goal {
+                let mut path = vec![goal];
+                let mut current = goal;
+                while let Some(previous) = came_from.get(&current) {
+                    current = *previous;
+                    path.push(current);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            searched += 1;
+            if searched > MAX_SEARCHED_TILES {
+                return None;
+            }
+            let cost = costs[&tile];
+            for (neighbour, move_cost) in neighbours(&self.collision, &self.terrain, tile) {
+                let new_cost = cost + move_cost;
+                if costs.get(&neighbour).map_or(true, |c| new_cost < *c) {
+                    costs.insert(neighbour, new_cost);
+                    came_from.insert(neighbour, tile);
+                    open.push(Reverse((new_cost + heuristic(neighbour), neighbour)));
+                }
+            }
+        }
+        None
+    }
+
+    //String pulling : from each point, goes straight to the farthest point in sight.
+    //A shortcut doesn't cross tiles costing more than those of the path it replaces,
+    //so the mobs still go around the costly tiles the search avoided.
+    fn smooth(&self, points: Vec<Vec2>) -> Vec<Vec2> {
+        let segment_costs: Vec<isize> = points
+            .windows(2)
+            .map(|segment| {
+                line_max_cost(&self.collision, &self.terrain, segment[0], segment[1]).unwrap_or(0)
+            })
+            .collect();
+        let mut smoothed = Vec::new();
+        let mut anchor = 0;
+        if let Some(first) = points.first() {
+            smoothed.push(*first);
+        }
+        while anchor + 1 < points.len() {
+            let farthest = (anchor + 2..points.len())
+                .rev()
+                .find(|i| {
+                    let replaced_cost = segment_costs[anchor..*i].iter().copied().max();
+                    line_max_cost(&self.collision, &self.terrain, points[anchor], points[*i])
+                        .map_or(false, |cost| Some(cost) <= replaced_cost)
+                })
+                .unwrap_or(anchor + 1);
+            smoothed.push(points[farthest]);
+            anchor = farthest;
+        }
+        smoothed
+    }
+}
+
+//The cost of the way to the player from every tile of an island.
+//It is shared by all the mobs of the island following it, and computed again when the player changes tiles.
+#[derive(Default)]
+pub struct DistanceField {
+    //the tile of the player
+    goal: Option<(i32, i32)>,
+    //the position of the player, where the mobs on their tile walk to
+    target: Vec2,
+    //in the same layout as the tiles, None where the player can't be reached from
+    distances: Vec<Vec<Option<isize>>>,
 }
+pub type SharedDistanceField = Arc<RwLock<DistanceField>>;
+impl DistanceField {
+    fn distance(&self, (x, y): (i32, i32)) -> Option<isize> {
+        if x < 0 || y < 0 {
+            return None;
+        }
+        *self.distances.get(x as usize)?.get(y as usize)?
+    }
 
-//Get the cost of walking over the tile according to the sprite id.
-//Might be a good idea to add some sort of parameter to that function so different behaviour can exist.
-pub fn get_tile_cost(tile: &Tile) -> isize {
-    match tile.sprite_id.unwrap() {
-        0 => 1,
-        _ => 1,
+    //Dijkstra from the player over the whole island
+    fn compute(&mut self, tiles: &[Vec<Tile>], terrain: &TerrainProfile, goal: (i32, i32)) {
+        self.goal = Some(goal);
+        self.distances = tiles
+            .iter()
+            .map(|column| vec![None; column.len()])
+            .collect();
+        if tile_at(tiles, goal).is_none() {
+            return;
+        }
+        self.distances[goal.0 as usize][goal.1 as usize] = Some(0);
+        let mut open = BinaryHeap::new();
+        open.push(Reverse((0, goal)));
+        while let Some(Reverse((distance, tile))) = open.pop() {
+            if self.distance(tile).map_or(false, |d| distance > d) {
+                continue;
+            }
+            for (neighbour, cost) in neighbours(tiles, terrain, tile) {
+                let new_distance = distance + cost;
+                if self.distance(neighbour).map_or(true, |d| new_distance < d) {
+                    self.distances[neighbour.0 as usize][neighbour.1 as usize] = Some(new_distance);
+                    open.push(Reverse((new_distance, neighbour)));
+                }
+            }
+        }
     }
 }
 
-//get a pathfinder struct for the current island and with the choosen pathfinding algorithm
-pub fn get_pathfinding(
-    collision: &Arc<Vec<Vec<Tile>>>,
-    pathfinding_type: PathfindingType,
-) -> Pathfinder {
-    match pathfinding_type {
-        PathfindingType::None => Pathfinder::None(NoPathfinding),
-        PathfindingType::LineOfSight(view_distance) => Pathfinder::LineOfSight(LineOfSight {
+#[derive(Clone)]
+pub struct FlowField {
+    /// flow field pathfinding, for crowds of mobs
+    /// Instead of searching its own path, the mob walks down the distance field of the island,
+    /// to the neighbouring tile the closest to the player.
+    /// The first mob to see that the player changed tiles computes the field again for all of them.
+    pub view_distance: f32,
+    pub position: Vec2,
+    pub following: bool,
+    pub field: SharedDistanceField,
+    pub collision: Arc<Vec<Vec<Tile>>>,
+    pub terrain: TerrainProfile,
+}
+impl FlowField {
+    pub fn build(
+        params: &Value,
+        context: &PathfindingContext,
+    ) -> Result<Box<dyn Pathfinding>, ron::de::Error> {
+        let ViewDistance { view_distance } = params.clone().into_rust()?;
+        Ok(Box::new(FlowField {
             view_distance,
-            collision: collision.clone(),
-            ..Default::default()
-        }),
+            position: Vec2::zero(),
+            following: false,
+            field: context.distance_field.clone(),
+            collision: context.collision.clone(),
+            terrain: context.terrain.clone(),
+        }))
     }
 }
+
+impl Pathfinding for FlowField {
+    fn find_path(&mut self, mob_pos: Vec2, player_pos: Vec2) -> Result<(), NoPathError> {
+        self.following = false;
+        if (player_pos - mob_pos).length() > self.view_distance {
+            return Err(NoPathError);
+        }
+        let goal = tile_coord(player_pos);
+        let mut field = self.field.write().unwrap();
+        if field.goal != Some(goal) {
+            field.compute(&self.collision, &self.terrain, goal);
+        }
+        field.target = player_pos;
+        field.distance(tile_coord(mob_pos)).ok_or(NoPathError)?;
+        self.position = mob_pos;
+        self.following = true;
+        Ok(())
+    }
+
+    fn step(&mut self, speed: f32, delta_time: f32) -> Result<Vec2, PathFinishedError> {
+        if !self.following {
+            return Err(PathFinishedError);
+        }
+        let field = self.field.read().unwrap();
+        let tile = tile_coord(self.position);
+        let target = if field.goal == Some(tile) {
+            field.target
+        } else {
+            let (_, next) = neighbours(&self.collision, &self.terrain, tile)
+                .into_iter()
+                .filter_map(|(neighbour, _)| Some((field.distance(neighbour)?, neighbour)))
+                .min()
+                .ok_or(PathFinishedError)?;
+            (Vec2::new(next.0 as f32, next.1 as f32) + Vec2::new(0.5, 0.5)) * TILE
+        };
+        let to_target = target - self.position;
+        let distance = speed * delta_time * TILE;
+        if to_target.length() <= distance {
+            self.position = target;
+            if target == field.target {
+                self.following = false;
+            }
+        } else {
+            self.position += to_target.normalize() * distance;
+        }
+        Ok(self.position)
+    }
+
+    fn box_clone(&self) -> Box<dyn Pathfinding> {
+        Box::new(self.clone())
+    }
+}
+
+//Get the cost of walking over the tile according to the terrain profile, 0 when it is blocking.
+//Each mob has its profile in config/mobs.ron, the land player has its own in config/terrain.ron.
+pub fn get_tile_cost(tile: &Tile, terrain: &TerrainProfile) -> isize {
+    terrain.cost(tile.kind).map_or(0, |cost| cost as isize)
+}
@@ -2,11 +2,15 @@ use bevy::{prelude::*, render::camera::Camera};
 use std::f32::consts::PI;
 
 use crate::{
+    clock::Tinted,
+    lighting::{LightKind, LightSource},
     loading::GameState,
+    particles::{ParticleEmitter, ParticleKind},
     sea::{loader::SeaHandles, player::PlayerPositionUpdate, ISLAND_SCALING, TILE_SIZE},
 };
 
 use super::{
+    collision::{slide, LandTerrain, TerrainConfig},
     loader::{LandHandles, UnloadLandFlag},
     LAND_SCALING,
 };
@@ -28,6 +32,7 @@ impl Plugin for LandPlayerPlugin {
                 keyboard_input_system.system(),
             )
             .on_state_update(GameState::STAGE, GameState::Land, camera_system.system())
+            .on_state_update(GameState::STAGE, GameState::Land, dust_system.system())
             .add_event::<PlayerMovedEvent>()
             .insert_resource(CameraTransition {
                 t: 0.,
@@ -86,8 +91,12 @@ fn load_system(
             ..Default::default()
         })
         .with(Player::default())
+        .with(Tinted(Color::WHITE))
+        .with(ParticleEmitter::new(ParticleKind::Dust))
         .spawn(SpriteSheetBundle {
-            texture_atlas: sea_handles.boat.clone(),
+            texture_atlas: sea_handles.ships[sea_player_pos.ship_class]
+                .sprite_sheet
+                .clone(),
             transform: Transform {
                 translation: Vec3::new(boat_x, boat_y, 99.),
                 scale: Vec3::new(
@@ -107,6 +116,8 @@ fn load_system(
             },
             ..Default::default()
         })
+        .with(Tinted(Color::WHITE))
+        .with(LightSource(LightKind::Lantern))
         .with(UnloadLandFlag);
 }
 
@@ -147,9 +158,13 @@ fn keyboard_input_system(
 }
 
 const UPDATES_PER_TILE: f32 = 10.;
+//the half size of the box colliding with the terrain, centered on the player
+const PLAYER_HALF_SIZE: (f32, f32) = (14., 10.);
 fn player_movement(
     mut last_pos: Local<Vec3>,
     time: Res<Time>,
+    terrain: Res<LandTerrain>,
+    terrain_config: Res<TerrainConfig>,
     mut events: ResMut<Events<PlayerMovedEvent>>,
     mut player_query: Query<(&Player, &mut Transform)>,
 ) {
@@ -157,8 +172,17 @@ fn player_movement(
     for (player, mut player_transform) in player_query.iter_mut() {
         let rounded_angle = (0.5 + 8. * player.rotation / (2. * PI)).floor() / 8.0 * (2. * PI);
         let (s, c) = f32::sin_cos(rounded_angle);
-        player_transform.translation.x += c * player.speed * time.delta_seconds();
-        player_transform.translation.y += s * player.speed * time.delta_seconds();
+        let movement = Vec2::new(c, s) * player.speed * time.delta_seconds();
+        //the player stays on the island, sliding along the coasts and what it can't walk on
+        let position = slide(
+            &terrain.0,
+            &terrain_config.player,
+            player_transform.translation.truncate(),
+            movement,
+            PLAYER_HALF_SIZE.into(),
+        );
+        player_transform.translation.x = position.x;
+        player_transform.translation.y = position.y;
         let current_tile = (player_transform.translation / TILE * UPDATES_PER_TILE).floor();
         if current_tile.x as i32 != last_pos.x as i32 || current_tile.y as i32 != last_pos.y as i32
         {
@@ -168,6 +192,25 @@ fn player_movement(
     }
 }
 
+//dust particles per second while walking
+const DUST_RATE: f32 = 8.;
+//from the center of the player to its feet, in pixels
+const FEET_OFFSET: f32 = 12.;
+
+//The player kicks up dust behind them while walking
+fn dust_system(mut player_query: Query<(&Player, &mut ParticleEmitter)>) {
+    for (player, mut emitter) in player_query.iter_mut() {
+        if player.speed <= 0. {
+            emitter.rate = 0.;
+            continue;
+        }
+        let (s, c) = f32::sin_cos(player.rotation);
+        emitter.rate = DUST_RATE;
+        emitter.offset = Vec2::new(0., -FEET_OFFSET);
+        emitter.direction = -Vec2::new(c, s);
+    }
+}
+
 pub struct CameraTransition {
     t: f32,
     destination: Vec3,
@@ -0,0 +1,40 @@
+#version 450
+layout(location = 0) in vec4 world_position;
+layout(location = 0) out vec4 o_Target;
+layout(set = 2, binding = 0) uniform Lighting_ambient {
+    float ambient;
+};
+layout(set = 2, binding = 1) uniform Lighting_night {
+    vec4 night;
+};
+// two vec4 per light : x, y, radius, intensity then r, g, b, unused
+layout(set = 2, binding = 2) readonly buffer Lighting_lights {
+    vec4 Lights[];
+};
+
+void main() {
+    vec2 p = world_position.xy;
+    float light = 0.;
+    vec3 glow = vec3(0.);
+    for (int i = 0; i + 1 < Lights.length(); i += 2) {
+        vec4 source = Lights[i];
+        if (source.z <= 0.) {
+            continue;
+        }
+        // a simple radial falloff
+        float falloff = clamp(1. - distance(p, source.xy) / source.z, 0., 1.);
+        falloff *= falloff * source.w;
+        light += falloff;
+        glow += Lights[i + 1].rgb * falloff;
+    }
+
+    // the share of the scene hidden by the night, and the colour added by the lights
+    float darkness = 1. - clamp(ambient + light * (1. - ambient), 0., 1.);
+    float glow_alpha = clamp(light, 0., 1.) * (1. - ambient) * 0.25;
+    float alpha = darkness + glow_alpha;
+    if (alpha <= 0.001) {
+        discard;
+    }
+    vec3 color = (night.rgb * darkness + glow / max(light, 0.001) * glow_alpha) / alpha;
+    o_Target = vec4(color, alpha);
+}
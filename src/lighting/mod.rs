@@ -0,0 +1,226 @@
+use std::{collections::HashMap, f32::consts::PI};
+
+use bevy::{
+    prelude::*,
+    reflect::TypeUuid,
+    render::{
+        camera::Camera,
+        pipeline::{PipelineDescriptor, RenderPipeline},
+        render_graph::{
+            base::{self, MainPass},
+            RenderGraph, RenderResourcesNode,
+        },
+        renderer::RenderResources,
+        shader::{ShaderStage, ShaderStages},
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::clock::GameClock;
+
+//The night is a plane over the scene following the camera, like the sea.
+//It darkens everything but the surroundings of the light sources.
+pub struct LightingPlugin;
+impl Plugin for LightingPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        {
+            let resources = app.resources_mut();
+            let mut render_graph = resources.get_mut::<RenderGraph>().unwrap();
+            render_graph.add_lighting_graph(resources);
+        }
+        app.insert_resource(read_lighting_config())
+            .add_startup_system(spawn_lighting_system.system())
+            .add_system(lighting_system.system());
+    }
+}
+
+const VERTEX_SHADER: &str = include_str!("../background/sea_bg.vert");
+
+const FRAGMENT_SHADER: &str = include_str!("lighting.frag");
+
+pub const LIGHTING_PIPELINE_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(PipelineDescriptor::TYPE_UUID, 0x9a47d3c0f25e61b8);
+
+//over the weather, under the camera
+const LIGHTING_Z: f32 = 600.;
+//the lights sent to the shader, the closest to the camera first
+const MAX_LIGHTS: usize = 64;
+
+fn build_lighting_pipeline(shaders: &mut Assets<Shader>) -> PipelineDescriptor {
+    PipelineDescriptor::default_config(ShaderStages {
+        vertex: shaders.add(Shader::from_glsl(ShaderStage::Vertex, VERTEX_SHADER)),
+        fragment: Some(shaders.add(Shader::from_glsl(ShaderStage::Fragment, FRAGMENT_SHADER))),
+    })
+}
+
+pub trait LightingRenderGraphBuilder {
+    fn add_lighting_graph(&mut self, resources: &Resources) -> &mut Self;
+}
+
+impl LightingRenderGraphBuilder for RenderGraph {
+    fn add_lighting_graph(&mut self, resources: &Resources) -> &mut Self {
+        self.add_system_node("lighting", RenderResourcesNode::<Lighting>::new(true));
+        self.add_node_edge("lighting", base::node::MAIN_PASS)
+            .unwrap();
+        let mut pipelines = resources.get_mut::<Assets<PipelineDescriptor>>().unwrap();
+        let mut shaders = resources.get_mut::<Assets<Shader>>().unwrap();
+        pipelines.set_untracked(
+            LIGHTING_PIPELINE_HANDLE,
+            build_lighting_pipeline(&mut shaders),
+        );
+        self
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum LightKind {
+    Lantern,
+    Campfire,
+    PortLight,
+    Lighthouse,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LightConfig {
+    //in the units of the entity holding the light, so the lights of an island grow with it
+    pub radius: f32,
+    //rgb between 0 and 1
+    pub color: (f32, f32, f32),
+    pub intensity: f32,
+    //how much the intensity wavers, from 0 to 1
+    pub flicker: f32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LightingConfig {
+    //how lit the scene is at midnight without lights, from 0 to 1
+    pub night_ambient: f32,
+    //the colour of the dark
+    pub night_color: (f32, f32, f32),
+    pub lights: HashMap<LightKind, LightConfig>,
+}
+
+fn read_lighting_config() -> LightingConfig {
+    let lighting_config_string =
+        std::fs::read_to_string("config/lighting.ron").expect("lighting config file not found");
+    ron::from_str(&lighting_config_string).expect("syntax error on lighting config file")
+}
+
+//A light source, shining around the entity
+pub struct LightSource(pub LightKind);
+
+//The uniforms of the lighting shader
+#[derive(Debug, Default, RenderResources, TypeUuid, Reflect)]
+#[uuid = "3f0c85e4-6a3d-4b91-9d2e-8b51c7a0e6f2"]
+pub struct Lighting {
+    //from 0, the scene is all dark, to 1, the lighting is invisible
+    pub ambient: f32,
+    pub night: Color,
+    //two per light : x, y, radius and intensity, then the colour
+    #[render_resources(buffer)]
+    pub lights: Vec<Vec4>,
+}
+
+#[derive(Bundle)]
+struct LightingBundle {
+    draw: Draw,
+    visible: Visible,
+    render_pipelines: RenderPipelines,
+    main_pass: MainPass,
+    mesh: Handle<Mesh>,
+    transform: Transform,
+    global_transform: GlobalTransform,
+    lighting: Lighting,
+}
+
+fn spawn_lighting_system(commands: &mut Commands, mut meshes: ResMut<Assets<Mesh>>) {
+    let mut transform = Transform::from_rotation(Quat::from_rotation_x(PI / 2.));
+    transform.translation.z = LIGHTING_Z;
+    commands.spawn(LightingBundle {
+        draw: Default::default(),
+        visible: Visible {
+            is_visible: true,
+            is_transparent: true,
+        },
+        render_pipelines: RenderPipelines::from_pipelines(vec![RenderPipeline::new(
+            LIGHTING_PIPELINE_HANDLE.typed(),
+        )]),
+        main_pass: MainPass,
+        mesh: meshes.add(Mesh::from(shape::Plane { size: 10000.0 })),
+        transform,
+        global_transform: Default::default(),
+        lighting: Lighting {
+            ambient: 1.,
+            night: Color::BLACK,
+            //the buffer can't be empty
+            lights: vec![Vec4::zero(); 2],
+        },
+    });
+}
+
+fn lighting_system(
+    time: Res<Time>,
+    windows: Res<Windows>,
+    clock: Res<GameClock>,
+    config: Res<LightingConfig>,
+    camera_query: Query<&Transform, With<Camera>>,
+    light_query: Query<(Entity, &LightSource, &GlobalTransform)>,
+    mut lighting_query: Query<(&mut Lighting, &mut Transform), Without<Camera>>,
+) {
+    let camera_transform = match camera_query.iter().next() {
+        Some(camera_transform) => *camera_transform,
+        None => return,
+    };
+    let camera = camera_transform.translation.truncate();
+    let half_screen = windows.get_primary().map_or(Vec2::zero(), |window| {
+        Vec2::new(window.width(), window.height()) / 2.
+    }) * camera_transform.scale.truncate();
+
+    let mut lights: Vec<(f32, Vec4, Vec4)> = light_query
+        .iter()
+        .filter_map(|(entity, LightSource(kind), transform)| {
+            let light = config.lights.get(kind)?;
+            let position = transform.translation.truncate();
+            let radius = light.radius * transform.scale.x;
+            //the lights out of the screen are skipped
+            let offset = (position - camera).abs() - half_screen;
+            if offset.x > radius || offset.y > radius {
+                return None;
+            }
+            //each light wavers with its own phase
+            let phase = entity.id() as f32 * 1.7;
+            let flicker = 1.
+                - light.flicker
+                    * (0.5
+                        + 0.25 * f32::sin(time.seconds_since_startup() as f32 * 7. + phase)
+                        + 0.25 * f32::sin(time.seconds_since_startup() as f32 * 13. + phase));
+            let (r, g, b) = light.color;
+            Some((
+                position.distance(camera),
+                Vec4::new(position.x, position.y, radius, light.intensity * flicker),
+                Vec4::new(r, g, b, 0.),
+            ))
+        })
+        .collect();
+    lights.sort_by(|(a, _, _), (b, _, _)| a.partial_cmp(b).unwrap());
+    lights.truncate(MAX_LIGHTS);
+
+    let daylight = clock.daylight();
+    let ambient = config.night_ambient + (1. - config.night_ambient) * daylight;
+    let (r, g, b) = config.night_color;
+    for (mut lighting, mut transform) in lighting_query.iter_mut() {
+        transform.translation.x = camera.x;
+        transform.translation.y = camera.y;
+        transform.scale = camera_transform.scale;
+        lighting.ambient = ambient;
+        lighting.night = Color::rgb(r, g, b);
+        lighting.lights.clear();
+        for (_, source, color) in lights.iter() {
+            lighting.lights.push(*source);
+            lighting.lights.push(*color);
+        }
+        if lighting.lights.is_empty() {
+            lighting.lights = vec![Vec4::zero(); 2];
+        }
+    }
+}
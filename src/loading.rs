@@ -1,6 +1,7 @@
 use std::path::PathBuf;
 
 use bevy::prelude::*;
+use serde::{de::DeserializeOwned, Serialize};
 
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub enum GameState {
@@ -13,6 +14,20 @@ impl GameState {
 }
 
 pub struct SavePath(pub PathBuf);
+impl SavePath {
+    //Reads a save file, None if it doesn't exist or is not readable
+    pub fn load<T: DeserializeOwned>(&self, name: &str) -> Option<T> {
+        let save_string = std::fs::read_to_string(self.0.join(name)).ok()?;
+        ron::from_str(&save_string).ok()
+    }
+
+    pub fn save<T: Serialize>(&self, name: &str, value: &T) -> std::io::Result<()> {
+        let save_string =
+            ron::to_string(value).map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        std::fs::create_dir_all(&self.0)?;
+        std::fs::write(self.0.join(name), save_string)
+    }
+}
 pub struct LoaderPlugin;
 impl Plugin for LoaderPlugin {
     fn build(&self, app: &mut AppBuilder) {
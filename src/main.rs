@@ -1,9 +1,12 @@
 #![allow(clippy::too_many_arguments)]
 mod background;
 mod character;
+mod clock;
 mod land;
+mod lighting;
 mod loading;
 mod menu;
+mod particles;
 mod sea;
 mod util;
 use background::SeaBackgroundPlugin;
@@ -19,12 +22,21 @@ use bevy::{
 use bevy::{prelude::*, render::camera::OrthographicProjection};
 use bevy_egui::EguiPlugin;
 use character::CharacterPlugin;
+use clock::ClockPlugin;
 use land::LandPlugin;
+use lighting::LightingPlugin;
 use menu::MenuPlugin;
+use particles::ParticlesPlugin;
 use util::SeededHasher;
 
+//the default camera scale, the sea camera zooms from there
 pub const ZOOM: f32 = 1.;
 fn main() {
+    //prebuilds the boat hull meshes cache without starting the game
+    if std::env::args().any(|arg| arg == "--build-hull-cache") {
+        sea::hull_cache::build_hull_cache();
+        return;
+    }
     App::build()
         .insert_resource(WindowDescriptor {
             title: "I am a window!".to_string(),
@@ -57,6 +69,9 @@ fn main() {
         .add_plugin(LandPlugin)
         .add_plugin(MenuPlugin)
         .add_plugin(CharacterPlugin)
+        .add_plugin(ClockPlugin)
+        .add_plugin(LightingPlugin)
+        .add_plugin(ParticlesPlugin)
         .insert_resource(100u32)
         // Any plugin can register diagnostics
         .run();
@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+
+use bevy::{
+    prelude::*,
+    render::texture::{Extent3d, TextureDimension, TextureFormat},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{clock::GameClock, loading::GameState, util::next_random};
+
+//Small short-lived sprites, spawned by the emitters on entities and by the bursts sent as events.
+//Any plugin can add a `ParticleEmitter` to an entity or send a `ParticleBurst`.
+pub struct ParticlesPlugin;
+impl Plugin for ParticlesPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(read_particles_config())
+            .add_event::<ParticleBurst>()
+            .add_startup_system(setup_particles_system.system())
+            .add_system(emit_system.system())
+            .add_system(particle_system.system());
+        for state in [GameState::Sea, GameState::Land].iter() {
+            app.on_state_exit(
+                GameState::STAGE,
+                state.clone(),
+                clear_particles_system.system(),
+            );
+        }
+    }
+}
+
+//the side of the particle texture, the sizes are relative to it
+const DOT_SIZE: u32 = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ParticleKind {
+    Wake,
+    Splash,
+    Spray,
+    Dust,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ParticleConfig {
+    //in seconds, each particle lives between the two
+    pub lifetime: (f32, f32),
+    //in pixels per second, between the two
+    pub speed: (f32, f32),
+    //the particles go up to this angle away from the direction of the emitter
+    pub spread: f32,
+    //in pixels, at the birth and at the death of the particle
+    pub size: (f32, f32),
+    //rgba between 0 and 1, the alpha fading to 0 along the life
+    pub color: (f32, f32, f32, f32),
+    //the share of the speed lost every second
+    pub drag: f32,
+    pub z: f32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ParticlesConfig {
+    //no particle is spawned over this count
+    pub max_particles: usize,
+    pub effects: HashMap<ParticleKind, ParticleConfig>,
+}
+
+fn read_particles_config() -> ParticlesConfig {
+    let particles_config_string =
+        std::fs::read_to_string("config/particles.ron").expect("particles config file not found");
+    ron::from_str(&particles_config_string).expect("syntax error on particles config file")
+}
+
+//Spawns particles around the entity as long as its rate is above 0
+pub struct ParticleEmitter {
+    pub kind: ParticleKind,
+    //particles per second
+    pub rate: f32,
+    //where the particles start, from the entity
+    pub offset: Vec2,
+    //where the particles go
+    pub direction: Vec2,
+    //the particles owed since the last one, as the rate rarely matches the frames
+    pending: f32,
+}
+impl ParticleEmitter {
+    pub fn new(kind: ParticleKind) -> Self {
+        ParticleEmitter {
+            kind,
+            rate: 0.,
+            offset: Vec2::zero(),
+            direction: Vec2::unit_y(),
+            pending: 0.,
+        }
+    }
+}
+
+//Spawns `count` particles at once
+pub struct ParticleBurst {
+    pub kind: ParticleKind,
+    pub position: Vec2,
+    pub direction: Vec2,
+    pub count: u32,
+}
+
+struct Particle {
+    kind: ParticleKind,
+    velocity: Vec2,
+    age: f32,
+    lifetime: f32,
+}
+
+//The texture shared by all the particles
+struct ParticleAtlas(Handle<TextureAtlas>);
+
+//A white dot fading out to its edge
+fn build_dot_texture() -> Texture {
+    let center = (DOT_SIZE as f32 - 1.) / 2.;
+    let mut data = Vec::with_capacity((DOT_SIZE * DOT_SIZE * 4) as usize);
+    for y in 0..DOT_SIZE {
+        for x in 0..DOT_SIZE {
+            let distance = Vec2::new(x as f32 - center, y as f32 - center).length() / center;
+            let alpha = (1. - distance).max(0.).min(1.).powf(0.7);
+            data.extend_from_slice(&[255, 255, 255, (alpha * 255.) as u8]);
+        }
+    }
+    Texture::new(
+        Extent3d::new(DOT_SIZE, DOT_SIZE, 1),
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
+
+fn setup_particles_system(
+    commands: &mut Commands,
+    mut textures: ResMut<Assets<Texture>>,
+    mut atlases: ResMut<Assets<TextureAtlas>>,
+) {
+    let texture = textures.add(build_dot_texture());
+    let atlas = TextureAtlas::from_grid(texture, Vec2::new(DOT_SIZE as f32, DOT_SIZE as f32), 1, 1);
+    commands.insert_resource(ParticleAtlas(atlases.add(atlas)));
+}
+
+fn spawn_particle(
+    commands: &mut Commands,
+    atlas: &ParticleAtlas,
+    kind: ParticleKind,
+    config: &ParticleConfig,
+    position: Vec2,
+    direction: Vec2,
+    counter: &mut u64,
+) {
+    let angle =
+        f32::atan2(direction.y, direction.x) + (2. * next_random(counter) - 1.) * config.spread;
+    let speed = config.speed.0 + (config.speed.1 - config.speed.0) * next_random(counter);
+    let lifetime =
+        config.lifetime.0 + (config.lifetime.1 - config.lifetime.0) * next_random(counter);
+    let (r, g, b, a) = config.color;
+    commands
+        .spawn(SpriteSheetBundle {
+            texture_atlas: atlas.0.clone(),
+            sprite: TextureAtlasSprite {
+                index: 0,
+                color: Color::rgba(r, g, b, a),
+            },
+            transform: Transform {
+                translation: position.extend(config.z),
+                scale: Vec3::new(config.size.0, config.size.0, DOT_SIZE as f32) / DOT_SIZE as f32,
+                ..Default::default()
+            },
+            visible: Visible {
+                is_visible: true,
+                is_transparent: true,
+            },
+            ..Default::default()
+        })
+        .with(Particle {
+            kind,
+            velocity: Vec2::new(angle.cos(), angle.sin()) * speed,
+            age: 0.,
+            lifetime,
+        });
+}
+
+fn emit_system(
+    commands: &mut Commands,
+    time: Res<Time>,
+    config: Res<ParticlesConfig>,
+    atlas: Res<ParticleAtlas>,
+    mut counter: Local<u64>,
+    mut burst_reader: EventReader<ParticleBurst>,
+    mut emitter_query: Query<(&mut ParticleEmitter, &GlobalTransform)>,
+    particle_query: Query<Entity, With<Particle>>,
+) {
+    let mut room = config
+        .max_particles
+        .saturating_sub(particle_query.iter().count());
+    for burst in burst_reader.iter() {
+        let effect = match config.effects.get(&burst.kind) {
+            Some(effect) => effect,
+            None => continue,
+        };
+        for _ in 0..burst.count {
+            if room == 0 {
+                break;
+            }
+            room -= 1;
+            spawn_particle(
+                commands,
+                &atlas,
+                burst.kind,
+                effect,
+                burst.position,
+                burst.direction,
+                &mut counter,
+            );
+        }
+    }
+    for (mut emitter, transform) in emitter_query.iter_mut() {
+        if emitter.rate <= 0. {
+            emitter.pending = 0.;
+            continue;
+        }
+        let effect = match config.effects.get(&emitter.kind) {
+            Some(effect) => effect,
+            None => continue,
+        };
+        emitter.pending += emitter.rate * time.delta_seconds();
+        let position = transform.translation.truncate() + emitter.offset;
+        while emitter.pending >= 1. {
+            emitter.pending -= 1.;
+            if room == 0 {
+                continue;
+            }
+            room -= 1;
+            spawn_particle(
+                commands,
+                &atlas,
+                emitter.kind,
+                effect,
+                position,
+                emitter.direction,
+                &mut counter,
+            );
+        }
+    }
+}
+
+//Moves the particles, grows and fades them, and removes them at the end of their life
+fn particle_system(
+    commands: &mut Commands,
+    time: Res<Time>,
+    clock: Res<GameClock>,
+    config: Res<ParticlesConfig>,
+    mut particle_query: Query<(
+        Entity,
+        &mut Particle,
+        &mut Transform,
+        &mut TextureAtlasSprite,
+    )>,
+) {
+    let delta = time.delta_seconds();
+    let tint = clock.tint();
+    for (entity, mut particle, mut transform, mut sprite) in particle_query.iter_mut() {
+        particle.age += delta;
+        let effect = match config.effects.get(&particle.kind) {
+            Some(effect) if particle.age < particle.lifetime => effect,
+            _ => {
+                commands.despawn(entity);
+                continue;
+            }
+        };
+        particle.velocity *= (1. - effect.drag * delta).max(0.);
+        transform.translation += (particle.velocity * delta).extend(0.);
+        let t = particle.age / particle.lifetime;
+        let size = effect.size.0 + (effect.size.1 - effect.size.0) * t;
+        transform.scale = Vec3::new(size, size, DOT_SIZE as f32) / DOT_SIZE as f32;
+        let (r, g, b, a) = effect.color;
+        sprite.color = Color::rgba(r * tint.r(), g * tint.g(), b * tint.b(), a * (1. - t));
+    }
+}
+
+fn clear_particles_system(commands: &mut Commands, particle_query: Query<Entity, With<Particle>>) {
+    for entity in particle_query.iter() {
+        commands.despawn(entity);
+    }
+}
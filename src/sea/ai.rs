@@ -0,0 +1,598 @@
+use std::{
+    collections::{HashMap, HashSet},
+    f32::consts::PI,
+    hash::Hasher,
+};
+
+use bevy::prelude::*;
+use seahash::SeaHasher;
+
+use crate::{
+    clock::{GameClock, Tinted},
+    lighting::{LightKind, LightSource},
+    loading::GameState,
+    util::{next_random, SeededHasher},
+};
+
+use super::{
+    collision::{hull_contact, SeaCollisionTree, StreamingArea},
+    combat::{Broadside, Cannons},
+    currents::Currents,
+    hull::{Hull, ShipImpactEvent, ShipSunkEvent},
+    loader::{SeaHandles, ShipsConfig},
+    map::Islands,
+    navigation::{find_route, follow_route, steer, ARRIVAL_DISTANCE},
+    player::{CollisionType, Player},
+    wind::Wind,
+    worldgen::GenRessources,
+    ISLAND_SCALING, TILE_SIZE,
+};
+
+pub struct SeaAiPlugin;
+impl Plugin for SeaAiPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<AiRegions>()
+            .on_state_update(GameState::STAGE, GameState::Sea, ai_regions_system.system())
+            .on_state_update(
+                GameState::STAGE,
+                GameState::Sea,
+                ai_collision_system.system(),
+            )
+            .on_state_update(GameState::STAGE, GameState::Sea, ai_control_system.system())
+            .on_state_update(
+                GameState::STAGE,
+                GameState::Sea,
+                ai_movement_system.system(),
+            )
+            .on_state_update(GameState::STAGE, GameState::Sea, ai_sunk_system.system())
+            .on_state_exit(GameState::STAGE, GameState::Sea, unload_ai_system.system());
+    }
+}
+
+//the side of a region, in tiles
+const REGION_SIZE: i32 = 16;
+//the chance for a region to have a ship, and to have two
+const SHIP_CHANCE: f32 = 0.25;
+const TWO_SHIPS_CHANCE: f32 = 0.05;
+const SPAWN_TRIES: usize = 8;
+//trade routes go to a region up to this many regions away
+const TRADE_DISTANCE: f32 = 6.;
+//distances in pixels
+const SIGHT_DISTANCE: f32 = 600.;
+const LOSE_SIGHT_DISTANCE: f32 = 900.;
+const PATROL_RADIUS: f32 = 400.;
+//the attackers turn broadside to their target under this distance
+const FIRING_DISTANCE: f32 = 300.;
+const CRUISE_SAIL: f32 = 0.75;
+//below this share of its hull, a ship runs away
+const FLEE_HEALTH: f32 = 0.3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShipKind {
+    Merchant,
+    Pirate,
+    Navy,
+}
+impl ShipKind {
+    //Whether ships of this kind attack a ship, None being the player
+    pub fn attacks(self, other: Option<ShipKind>) -> bool {
+        match self {
+            ShipKind::Merchant => false,
+            ShipKind::Pirate => other != Some(ShipKind::Pirate),
+            ShipKind::Navy => other == Some(ShipKind::Pirate),
+        }
+    }
+
+    //the tint of the sprite, to tell the ships apart
+    fn color(self) -> Color {
+        match self {
+            ShipKind::Merchant => Color::WHITE,
+            ShipKind::Pirate => Color::rgb(0.55, 0.45, 0.45),
+            ShipKind::Navy => Color::rgb(0.7, 0.8, 1.),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Behaviour {
+    //sails around its home, to the next corner of a square
+    Patrol(usize),
+    //sails back and forth between its home and another region
+    Trade { outbound: bool },
+    Chase(Entity),
+    Flee(Entity),
+}
+
+const PATROL_CORNERS: [(f32, f32); 4] = [(1., 1.), (-1., 1.), (-1., -1.), (1., -1.)];
+
+//A ship sailed by the computer. It moves with the same Player physics as the player's ship.
+#[derive(Clone)]
+pub struct AiShip {
+    pub kind: ShipKind,
+    pub behaviour: Behaviour,
+    home: Vec2,
+    trade_destination: Vec2,
+    pub(crate) collision: CollisionType,
+    //the route to the destination of the routine, and that destination
+    route: Vec<Vec2>,
+    route_target: Option<Vec2>,
+}
+impl AiShip {
+    //what the ship does when nothing is in sight
+    fn routine(&self) -> Behaviour {
+        match self.kind {
+            ShipKind::Merchant => Behaviour::Trade { outbound: true },
+            ShipKind::Pirate | ShipKind::Navy => Behaviour::Patrol(0),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct AiShipSave {
+    translation: Vec3,
+    player: Player,
+    hull: Hull,
+    ai: AiShip,
+}
+
+//The ships are generated per region, and saved per region when it is unloaded.
+//Every region generated once has its save, even empty, so it is never generated again.
+#[derive(Default)]
+struct AiRegions {
+    loaded: HashSet<(i32, i32)>,
+    saves: HashMap<(i32, i32), Vec<AiShipSave>>,
+}
+impl AiRegions {
+    //Keeps only the loaded regions matching `keep`, the others getting a save for their ships
+    fn unload(&mut self, keep: impl Fn((i32, i32)) -> bool) {
+        let saves = &mut self.saves;
+        self.loaded.retain(|region| {
+            if keep(*region) {
+                return true;
+            }
+            saves.entry(*region).or_insert_with(Vec::new);
+            false
+        });
+    }
+
+    //Saves a ship in the region it is in, generating that region first if it never was
+    fn save(
+        &mut self,
+        mut save: AiShipSave,
+        hasher: &SeededHasher,
+        gen_ressources: &GenRessources,
+        ships_config: &ShipsConfig,
+    ) {
+        //the entities chased or fled won't exist anymore
+        save.ai.behaviour = save.ai.routine();
+        save.ai.collision = CollisionType::None;
+        save.ai.route.clear();
+        save.ai.route_target = None;
+        let region = region_of(save.translation.truncate());
+        self.saves
+            .entry(region)
+            .or_insert_with(|| generate_region(region, hasher, gen_ressources, ships_config))
+            .push(save);
+    }
+}
+
+fn to_tile(position: Vec2) -> Vec2 {
+    (position / (TILE_SIZE as f32 * ISLAND_SCALING)).floor()
+}
+
+fn region_of(position: Vec2) -> (i32, i32) {
+    let tile = to_tile(position);
+    (
+        (tile.x as i32).div_euclid(REGION_SIZE),
+        (tile.y as i32).div_euclid(REGION_SIZE),
+    )
+}
+
+fn region_center_tile(region: (i32, i32)) -> (i32, i32) {
+    (
+        region.0 * REGION_SIZE + REGION_SIZE / 2,
+        region.1 * REGION_SIZE + REGION_SIZE / 2,
+    )
+}
+
+fn region_center(region: (i32, i32)) -> Vec2 {
+    let (x, y) = region_center_tile(region);
+    Vec2::new(x as f32, y as f32) * TILE_SIZE as f32 * ISLAND_SCALING
+}
+
+//The ships of a region. They only depend on the seed, so the land is checked with the noise of the islands.
+fn generate_region(
+    region: (i32, i32),
+    hasher: &SeededHasher,
+    gen_ressources: &GenRessources,
+    ships_config: &ShipsConfig,
+) -> Vec<AiShipSave> {
+    let mut hasher: SeaHasher = hasher.get_hasher();
+    hasher.write(b"sea_ships");
+    hasher.write_i32(region.0);
+    hasher.write_i32(region.1);
+    let mut random = hasher.finish();
+    let roll = next_random(&mut random);
+    let count = if roll < TWO_SHIPS_CHANCE {
+        2
+    } else if roll < SHIP_CHANCE {
+        1
+    } else {
+        0
+    };
+    let mut ships = Vec::new();
+    for _ in 0..count {
+        let kind = match next_random(&mut random) {
+            roll if roll < 0.5 => ShipKind::Merchant,
+            roll if roll < 0.8 => ShipKind::Pirate,
+            _ => ShipKind::Navy,
+        };
+        let class = ((next_random(&mut random) * ships_config.0.len() as f32) as usize)
+            .min(ships_config.0.len() - 1);
+        let rotation = next_random(&mut random) * 2. * PI;
+        let trade_region = (
+            region.0 + ((next_random(&mut random) * 2. - 1.) * TRADE_DISTANCE) as i32,
+            region.1 + ((next_random(&mut random) * 2. - 1.) * TRADE_DISTANCE) as i32,
+        );
+        //the tries are all drawn, so the next ships don't depend on which one succeeded
+        let tries: Vec<(i32, i32)> = (0..SPAWN_TRIES)
+            .map(|_| {
+                (
+                    region.0 * REGION_SIZE + (next_random(&mut random) * REGION_SIZE as f32) as i32,
+                    region.1 * REGION_SIZE + (next_random(&mut random) * REGION_SIZE as f32) as i32,
+                )
+            })
+            .collect();
+        let tile = tries.into_iter().find(|(x, y)| {
+            (-1..=1).all(|i| (-1..=1).all(|j| gen_ressources.is_sea((x + i, y + j))))
+        });
+        if let Some((x, y)) = tile {
+            let translation =
+                (Vec2::new(x as f32 + 0.5, y as f32 + 0.5) * TILE_SIZE as f32 * ISLAND_SCALING)
+                    .extend(100.);
+            let mut ai = AiShip {
+                kind,
+                behaviour: Behaviour::Patrol(0),
+                home: region_center(region),
+                trade_destination: region_center(trade_region),
+                collision: CollisionType::None,
+                route: Vec::new(),
+                route_target: None,
+            };
+            ai.behaviour = ai.routine();
+            ships.push(AiShipSave {
+                translation,
+                player: Player::new(class, &ships_config.0[class], rotation),
+                hull: Hull::new(ships_config.0[class].hull),
+                ai,
+            });
+        }
+    }
+    ships
+}
+
+fn spawn_ship(
+    commands: &mut Commands,
+    handles: &SeaHandles,
+    ships_config: &ShipsConfig,
+    save: AiShipSave,
+) {
+    let class = save.player.class;
+    commands
+        .spawn(SpriteSheetBundle {
+            texture_atlas: handles.ships[class].sprite_sheet.clone(),
+            sprite: TextureAtlasSprite {
+                color: save.ai.kind.color(),
+                ..Default::default()
+            },
+            transform: Transform::from_translation(save.translation),
+            ..Default::default()
+        })
+        .with(Tinted(save.ai.kind.color()))
+        .with(LightSource(LightKind::Lantern))
+        .with(save.player)
+        .with(save.hull)
+        .with(Cannons::new(ships_config.0[class].cannons))
+        .with(save.ai);
+}
+
+//Loads the regions around the player, and saves the ships leaving them
+fn ai_regions_system(
+    commands: &mut Commands,
+    hasher: Res<SeededHasher>,
+    gen_ressources: Local<GenRessources>,
+    handles: Res<SeaHandles>,
+    ships_config: Res<ShipsConfig>,
+    area: Res<StreamingArea>,
+    mut regions: ResMut<AiRegions>,
+    ship_query: Query<(Entity, &Transform, &Player, &Hull, &AiShip)>,
+) {
+    let (x, y) = area.center;
+    let in_area = |region: (i32, i32), (distance_x, distance_y): (i32, i32)| {
+        let (center_x, center_y) = region_center_tile(region);
+        (center_x - x).abs() <= distance_x && (center_y - y).abs() <= distance_y
+    };
+    //the regions are kept until they leave the despawn area, like the islands
+    regions.unload(|region| in_area(region, area.despawn_distance));
+    for (entity, transform, player, hull, ai) in ship_query.iter() {
+        if regions
+            .loaded
+            .contains(&region_of(transform.translation.truncate()))
+        {
+            continue;
+        }
+        let save = AiShipSave {
+            translation: transform.translation,
+            player: player.clone(),
+            hull: hull.clone(),
+            ai: ai.clone(),
+        };
+        regions.save(save, &hasher, &gen_ressources, &ships_config);
+        commands.despawn(entity);
+    }
+
+    let (spawn_x, spawn_y) = area.spawn_distance;
+    for region_x in (x - spawn_x).div_euclid(REGION_SIZE)..=(x + spawn_x).div_euclid(REGION_SIZE) {
+        for region_y in
+            (y - spawn_y).div_euclid(REGION_SIZE)..=(y + spawn_y).div_euclid(REGION_SIZE)
+        {
+            let region = (region_x, region_y);
+            if !in_area(region, area.spawn_distance) || !regions.loaded.insert(region) {
+                continue;
+            }
+            let ships = regions.saves.remove(&region).unwrap_or_else(|| {
+                generate_region(region, &hasher, &gen_ressources, &ships_config)
+            });
+            for save in ships {
+                spawn_ship(commands, &handles, &ships_config, save);
+            }
+        }
+    }
+}
+
+fn ai_collision_system(
+    handles: Res<SeaHandles>,
+    islands: Res<Islands>,
+    kdtree: Res<SeaCollisionTree>,
+    mut ship_query: Query<(&mut AiShip, &Player, &Transform, &TextureAtlasSprite)>,
+) {
+    for (mut ai, player, transform, sprite) in ship_query.iter_mut() {
+        ai.collision = match handles
+            .ships
+            .get(player.class)
+            .map(|ship| ship.meshes.get(sprite.index as usize))
+            .flatten()
+        {
+            Some(hull) => hull_contact(&islands, &kdtree, hull, transform.translation).0,
+            None => CollisionType::None,
+        };
+    }
+}
+
+//The closest ship closer than `sight` matching `wanted`, which gets the kind of the ship, None being the player
+fn closest_in_sight(
+    ships: &[(Entity, Vec2, Option<ShipKind>)],
+    entity: Entity,
+    position: Vec2,
+    sight: f32,
+    wanted: impl Fn(Option<ShipKind>) -> bool,
+) -> Option<Entity> {
+    ships
+        .iter()
+        .filter(|(other, _, kind)| *other != entity && wanted(*kind))
+        .map(|(other, other_position, _)| (*other, other_position.distance(position)))
+        .filter(|(_, distance)| *distance < sight)
+        .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+        .map(|(other, _)| other)
+}
+
+fn ai_control_system(
+    commands: &mut Commands,
+    handles: Res<SeaHandles>,
+    wind: Res<Wind>,
+    islands: Res<Islands>,
+    kdtree: Res<SeaCollisionTree>,
+    clock: Res<GameClock>,
+    mut queries: QuerySet<(
+        Query<(Entity, &Transform, &Hull, Option<&AiShip>), With<Player>>,
+        Query<(
+            Entity,
+            &mut AiShip,
+            &mut Player,
+            &Transform,
+            &Hull,
+            &mut Cannons,
+        )>,
+    )>,
+) {
+    let ships: Vec<(Entity, Vec2, Option<ShipKind>)> = queries
+        .q0()
+        .iter()
+        .filter(|(_, _, hull, _)| hull.health > 0.)
+        .map(|(entity, transform, _, ai)| {
+            (
+                entity,
+                transform.translation.truncate(),
+                ai.map(|ai| ai.kind),
+            )
+        })
+        .collect();
+    let position_of = |target: Entity| {
+        ships
+            .iter()
+            .find(|(entity, _, _)| *entity == target)
+            .map(|(_, position, _)| *position)
+    };
+    //the ships see less far at night
+    let (sight, lose_sight) = (
+        SIGHT_DISTANCE * clock.visibility(),
+        LOSE_SIGHT_DISTANCE * clock.visibility(),
+    );
+    for (entity, mut ai, mut player, transform, hull, mut cannons) in queries.q1_mut().iter_mut() {
+        if hull.health <= 0. {
+            continue;
+        }
+        let position = transform.translation.truncate();
+        let kind = ai.kind;
+        let in_sight = |target: Entity| {
+            position_of(target).map_or(false, |target| target.distance(position) < lose_sight)
+        };
+
+        let damaged = hull.health < FLEE_HEALTH * hull.max_health;
+        //a damaged ship also runs away from the ships it was attacking
+        let threat = closest_in_sight(&ships, entity, position, sight, |other| {
+            other.map_or(false, |other| other.attacks(Some(kind)))
+                || (damaged && kind.attacks(other))
+        })
+        .filter(|_| damaged || kind == ShipKind::Merchant);
+        let prey = closest_in_sight(&ships, entity, position, sight, |other| kind.attacks(other))
+            .filter(|_| !damaged);
+        ai.behaviour = match (ai.behaviour, threat, prey) {
+            (Behaviour::Flee(target), _, _) if in_sight(target) => Behaviour::Flee(target),
+            (_, Some(threat), _) => Behaviour::Flee(threat),
+            (Behaviour::Chase(target), _, _) if in_sight(target) && !damaged => {
+                Behaviour::Chase(target)
+            }
+            (_, _, Some(prey)) => Behaviour::Chase(prey),
+            (Behaviour::Chase(_), _, _) | (Behaviour::Flee(_), _, _) => ai.routine(),
+            (behaviour, _, _) => behaviour,
+        };
+
+        let wind = wind.at(position);
+        let (target, sail) = match ai.behaviour {
+            Behaviour::Patrol(corner) => {
+                let (x, y) = PATROL_CORNERS[corner];
+                (ai.home + Vec2::new(x, y) * PATROL_RADIUS, CRUISE_SAIL)
+            }
+            Behaviour::Trade { outbound } => {
+                let target = if outbound {
+                    ai.trade_destination
+                } else {
+                    ai.home
+                };
+                (target, CRUISE_SAIL)
+            }
+            Behaviour::Chase(target) => {
+                let target = position_of(target).unwrap_or(position);
+                let offset = target - position;
+                if let Some(side) = Broadside::facing(player.heading(), offset) {
+                    if offset.length() < FIRING_DISTANCE {
+                        cannons.fire(commands, &handles, side, entity, position, &player);
+                    }
+                }
+                //close enough, the ship turns so the guns bear
+                if offset.length() < FIRING_DISTANCE {
+                    (position + offset.perp(), 1.)
+                } else {
+                    (target, 1.)
+                }
+            }
+            Behaviour::Flee(threat) => {
+                let threat = position_of(threat).unwrap_or(position);
+                let away = (position - threat).normalize() * SIGHT_DISTANCE;
+                (position + away, 1.)
+            }
+        };
+        match ai.behaviour {
+            //the routine destinations are reached along a route
+            Behaviour::Patrol(_) | Behaviour::Trade { .. } => {
+                if ai.route_target != Some(target) {
+                    ai.route = find_route(&islands, &kdtree, position, target, ARRIVAL_DISTANCE);
+                    ai.route_target = Some(target);
+                }
+                //done when arrived, or as close as the known islands allow
+                if follow_route(
+                    &mut player,
+                    position,
+                    &mut ai.route,
+                    wind,
+                    &islands,
+                    &kdtree,
+                    sail,
+                ) {
+                    ai.behaviour = match ai.behaviour {
+                        Behaviour::Patrol(corner) => {
+                            Behaviour::Patrol((corner + 1) % PATROL_CORNERS.len())
+                        }
+                        Behaviour::Trade { outbound } => Behaviour::Trade {
+                            outbound: !outbound,
+                        },
+                        behaviour => behaviour,
+                    };
+                }
+            }
+            //the ships in sight are steered to directly
+            Behaviour::Chase(_) | Behaviour::Flee(_) => {
+                ai.route_target = None;
+                steer(&mut player, position, target, wind, &islands, &kdtree, sail);
+            }
+        }
+        //stuck against the rocks, the ship rows away
+        if matches!(ai.collision, CollisionType::Rigid) {
+            player.sail = 0.;
+            player.set_rowing(true);
+        } else {
+            player.set_rowing(false);
+        }
+    }
+}
+
+fn ai_movement_system(
+    time: Res<Time>,
+    wind: Res<Wind>,
+    currents: Res<Currents>,
+    kdtree: Res<SeaCollisionTree>,
+    mut impact_events: ResMut<Events<ShipImpactEvent>>,
+    mut ship_query: Query<(Entity, &AiShip, &mut Player, &mut Transform, &Hull)>,
+) {
+    for (entity, ai, mut player, mut transform, hull) in ship_query.iter_mut() {
+        if hull.health <= 0. {
+            continue;
+        }
+        let position = transform.translation.truncate();
+        if let Some(speed) = player.sail(
+            &mut transform,
+            &ai.collision,
+            hull.speed_factor(),
+            wind.at(position),
+            currents.at(position, &kdtree),
+            time.delta_seconds(),
+        ) {
+            impact_events.send(ShipImpactEvent { entity, speed });
+        }
+    }
+}
+
+//The sunk ships are gone for good, they are not saved with their region
+fn ai_sunk_system(
+    commands: &mut Commands,
+    mut sunk_reader: EventReader<ShipSunkEvent>,
+    ship_query: Query<Entity, With<AiShip>>,
+) {
+    for sunk in sunk_reader.iter() {
+        if ship_query.get(sunk.entity).is_ok() {
+            commands.despawn(sunk.entity);
+        }
+    }
+}
+
+fn unload_ai_system(
+    commands: &mut Commands,
+    hasher: Res<SeededHasher>,
+    gen_ressources: Local<GenRessources>,
+    ships_config: Res<ShipsConfig>,
+    mut regions: ResMut<AiRegions>,
+    ship_query: Query<(Entity, &Transform, &Player, &Hull, &AiShip)>,
+) {
+    regions.unload(|_| false);
+    for (entity, transform, player, hull, ai) in ship_query.iter() {
+        let save = AiShipSave {
+            translation: transform.translation,
+            player: player.clone(),
+            hull: hull.clone(),
+            ai: ai.clone(),
+        };
+        regions.save(save, &hasher, &gen_ressources, &ships_config);
+        commands.despawn(entity);
+    }
+}
@@ -0,0 +1,192 @@
+use bevy::{input::mouse::MouseWheel, prelude::*, render::camera::Camera};
+
+use crate::{loading::GameState, ZOOM};
+
+use super::{ai::AiShip, player::Player};
+
+pub struct SeaCameraPlugin;
+impl Plugin for SeaCameraPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<SeaCamera>()
+            .on_state_enter(
+                GameState::STAGE,
+                GameState::Sea,
+                enter_camera_system.system(),
+            )
+            .on_state_update(GameState::STAGE, GameState::Sea, zoom_system.system())
+            .on_state_update(GameState::STAGE, GameState::Sea, free_pan_system.system())
+            .on_state_update(GameState::STAGE, GameState::Sea, camera_system.system())
+            .on_state_exit(
+                GameState::STAGE,
+                GameState::Sea,
+                exit_camera_system.system(),
+            );
+    }
+}
+
+//the time the camera takes to catch up with the ship, in seconds
+const SMOOTH_TIME: f32 = 0.4;
+//the camera looks ahead where the ship will be in this many seconds
+const LOOK_AHEAD_TIME: f32 = 1.5;
+//at most this share of the half height of the screen
+const MAX_LOOK_AHEAD: f32 = 0.4;
+//further than this, in pixels, the camera jumps instead of gliding, like after a respawn
+const SNAP_DISTANCE: f32 = 3000.;
+const MIN_ZOOM: f32 = 0.5;
+const MAX_ZOOM: f32 = 3.;
+//zoom multiplier for a wheel notch, or per second while a key is held
+const WHEEL_ZOOM_STEP: f32 = 1.15;
+const KEY_ZOOM_SPEED: f32 = 2.;
+//how fast the zoom reaches its target
+const ZOOM_SMOOTHING: f32 = 8.;
+//in screen pixels per second
+const PAN_SPEED: f32 = 800.;
+
+//The state of the camera at sea. The zoom is kept while on land.
+pub struct SeaCamera {
+    //the zoom wanted, the camera scale goes toward it
+    pub zoom: f32,
+    //when set, the camera doesn't follow the ship and can be moved with WASD
+    pub free: bool,
+    velocity: Vec2,
+    snap: bool,
+}
+impl Default for SeaCamera {
+    fn default() -> Self {
+        SeaCamera {
+            zoom: ZOOM,
+            free: false,
+            velocity: Vec2::zero(),
+            snap: true,
+        }
+    }
+}
+
+//A critically damped spring going from `current` to `target`, without overshooting
+fn smooth_damp(current: Vec2, target: Vec2, velocity: &mut Vec2, delta: f32) -> Vec2 {
+    let omega = 2. / SMOOTH_TIME;
+    let x = omega * delta;
+    let decay = 1. / (1. + x + 0.48 * x * x + 0.235 * x * x * x);
+    let change = current - target;
+    let temp = (*velocity + omega * change) * delta;
+    *velocity = (*velocity - omega * temp) * decay;
+    target + (change + temp) * decay
+}
+
+fn enter_camera_system(mut sea_camera: ResMut<SeaCamera>) {
+    sea_camera.snap = true;
+    sea_camera.velocity = Vec2::zero();
+}
+
+//The land camera isn't zoomed
+fn exit_camera_system(mut camera_query: Query<&mut Transform, With<Camera>>) {
+    for mut camera_transform in camera_query.iter_mut() {
+        camera_transform.scale = ZOOM * Vec3::one();
+    }
+}
+
+//The mouse wheel and the + and - keys zoom
+fn zoom_system(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut wheel_reader: EventReader<MouseWheel>,
+    mut sea_camera: ResMut<SeaCamera>,
+    mut camera_query: Query<&mut Transform, With<Camera>>,
+) {
+    for wheel in wheel_reader.iter() {
+        sea_camera.zoom *= WHEEL_ZOOM_STEP.powf(-wheel.y.signum());
+    }
+    if keyboard_input.pressed(KeyCode::Equals) {
+        sea_camera.zoom /= KEY_ZOOM_SPEED.powf(time.delta_seconds());
+    } else if keyboard_input.pressed(KeyCode::Minus) {
+        sea_camera.zoom *= KEY_ZOOM_SPEED.powf(time.delta_seconds());
+    }
+    sea_camera.zoom = sea_camera.zoom.max(MIN_ZOOM).min(MAX_ZOOM);
+    let smoothing = 1. - (-ZOOM_SMOOTHING * time.delta_seconds()).exp();
+    for mut camera_transform in camera_query.iter_mut() {
+        let scale = camera_transform.scale.x;
+        let scale = if sea_camera.snap {
+            sea_camera.zoom
+        } else {
+            scale + (sea_camera.zoom - scale) * smoothing
+        };
+        camera_transform.scale = scale * Vec3::one();
+    }
+}
+
+//C toggles the free camera, moved with WASD
+fn free_pan_system(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut sea_camera: ResMut<SeaCamera>,
+    mut camera_query: Query<&mut Transform, With<Camera>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::C) {
+        sea_camera.free = !sea_camera.free;
+        sea_camera.velocity = Vec2::zero();
+    }
+    if !sea_camera.free {
+        return;
+    }
+    let mut direction = Vec2::zero();
+    if keyboard_input.pressed(KeyCode::W) {
+        direction.y += 1.;
+    }
+    if keyboard_input.pressed(KeyCode::S) {
+        direction.y -= 1.;
+    }
+    if keyboard_input.pressed(KeyCode::A) {
+        direction.x -= 1.;
+    }
+    if keyboard_input.pressed(KeyCode::D) {
+        direction.x += 1.;
+    }
+    for mut camera_transform in camera_query.iter_mut() {
+        let step = direction * PAN_SPEED * camera_transform.scale.x * time.delta_seconds();
+        camera_transform.translation += step.extend(0.);
+    }
+}
+
+//Follows the ship, a bit ahead of where it goes
+fn camera_system(
+    time: Res<Time>,
+    windows: Res<Windows>,
+    mut sea_camera: ResMut<SeaCamera>,
+    player_query: Query<(&Player, &Transform), Without<AiShip>>,
+    mut camera_query: Query<&mut Transform, With<Camera>>,
+) {
+    if sea_camera.free {
+        return;
+    }
+    let half_height = windows
+        .get_primary()
+        .map_or(540., |window| window.height() / 2.);
+    for (player, player_transform) in player_query.iter() {
+        let (s, c) = f32::sin_cos(player.heading());
+        for mut camera_transform in camera_query.iter_mut() {
+            let max_look_ahead = MAX_LOOK_AHEAD * half_height * camera_transform.scale.x;
+            let look_ahead = Vec2::new(c, s) * (player.speed() * LOOK_AHEAD_TIME);
+            let look_ahead = if look_ahead.length() > max_look_ahead {
+                look_ahead.normalize() * max_look_ahead
+            } else {
+                look_ahead
+            };
+            let target = player_transform.translation.truncate() + look_ahead;
+            let current = camera_transform.translation.truncate();
+            let position = if sea_camera.snap || current.distance(target) > SNAP_DISTANCE {
+                sea_camera.velocity = Vec2::zero();
+                target
+            } else {
+                smooth_damp(
+                    current,
+                    target,
+                    &mut sea_camera.velocity,
+                    time.delta_seconds(),
+                )
+            };
+            camera_transform.translation.x = position.x;
+            camera_transform.translation.y = position.y;
+        }
+        sea_camera.snap = false;
+    }
+}
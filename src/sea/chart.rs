@@ -0,0 +1,358 @@
+use std::{collections::HashMap, hash::Hasher, time::Duration};
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use seahash::SeaHasher;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    loading::{GameState, SavePath},
+    util::SeededHasher,
+};
+
+use super::{
+    ai::AiShip,
+    collision::SeaCollisionTree,
+    map::{Islands, TileKind},
+    player::{Player, PlayerPositionUpdate},
+    ISLAND_SCALING, TILE_SIZE,
+};
+
+pub struct SeaChartPlugin;
+impl Plugin for SeaChartPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<ChartView>()
+            .init_resource::<Chart>()
+            .on_state_enter(GameState::STAGE, GameState::Sea, load_chart_system.system())
+            .on_state_update(GameState::STAGE, GameState::Sea, explore_system.system())
+            .on_state_update(GameState::STAGE, GameState::Sea, minimap_system.system())
+            .on_state_update(GameState::STAGE, GameState::Sea, chart_system.system())
+            .on_state_update(GameState::STAGE, GameState::Sea, autosave_system.system())
+            .on_state_exit(GameState::STAGE, GameState::Sea, save_chart_system.system());
+    }
+}
+
+const CHART_FILE: &str = "chart.ron";
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
+//the side of a chart cell, in tiles
+const CELL_SIZE: i32 = 4;
+//the side of a chunk, in cells. Each row of a chunk is the bits of a u32.
+const CHUNK_SIZE: i32 = 32;
+//the cells this close to the ship are charted, in tiles
+const SIGHT_RADIUS: i32 = 24;
+//in screen pixels
+const MINIMAP_SIZE: f32 = 180.;
+const MINIMAP_CELL: f32 = 3.;
+const MIN_CHART_CELL: f32 = 1.;
+const MAX_CHART_CELL: f32 = 16.;
+const CHART_ZOOM_STEP: f32 = 1.25;
+
+const FOG_COLOR: egui::Color32 = egui::Color32::from_rgb(40, 46, 56);
+const SEA_COLOR: egui::Color32 = egui::Color32::from_rgb(70, 130, 180);
+const LAND_COLOR: egui::Color32 = egui::Color32::from_rgb(200, 180, 120);
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct ChartChunk {
+    explored: [u32; CHUNK_SIZE as usize],
+    land: [u32; CHUNK_SIZE as usize],
+}
+
+//What the player has seen of the sea, saved between sessions.
+//It keeps the coasts, so the islands stay on the chart once they are unloaded.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Chart {
+    //the chart of another world is discarded
+    seed: u64,
+    chunks: HashMap<(i32, i32), ChartChunk>,
+}
+impl Chart {
+    fn locate(cell: (i32, i32)) -> ((i32, i32), usize, u32) {
+        let chunk = (cell.0.div_euclid(CHUNK_SIZE), cell.1.div_euclid(CHUNK_SIZE));
+        let row = cell.1.rem_euclid(CHUNK_SIZE) as usize;
+        let bit = 1 << cell.0.rem_euclid(CHUNK_SIZE);
+        (chunk, row, bit)
+    }
+
+    //None if the cell is unexplored, otherwise whether there is land
+    pub fn cell(&self, cell: (i32, i32)) -> Option<bool> {
+        let (chunk, row, bit) = Chart::locate(cell);
+        let chunk = self.chunks.get(&chunk)?;
+        if chunk.explored[row] & bit == 0 {
+            None
+        } else {
+            Some(chunk.land[row] & bit != 0)
+        }
+    }
+
+    fn explore(&mut self, cell: (i32, i32), land: bool) {
+        let (chunk, row, bit) = Chart::locate(cell);
+        let chunk = self.chunks.entry(chunk).or_default();
+        chunk.explored[row] |= bit;
+        if land {
+            chunk.land[row] |= bit;
+        }
+    }
+}
+
+fn chart_seed(hasher: &SeededHasher) -> u64 {
+    let mut hasher: SeaHasher = hasher.get_hasher();
+    hasher.write(b"sea_chart");
+    hasher.finish()
+}
+
+fn to_cell(translation: Vec3) -> Vec2 {
+    translation.truncate() / (TILE_SIZE as f32 * ISLAND_SCALING * CELL_SIZE as f32)
+}
+
+//The seed is only known once the menu is left, so the chart is loaded when the sea is entered.
+//The chart of the current world is kept from one visit of the sea to the next.
+fn load_chart_system(
+    mut chart: ResMut<Chart>,
+    save_path: Res<SavePath>,
+    hasher: Res<SeededHasher>,
+) {
+    let seed = chart_seed(&hasher);
+    if chart.seed == seed {
+        return;
+    }
+    *chart = save_path
+        .load::<Chart>(CHART_FILE)
+        .filter(|chart| chart.seed == seed)
+        .unwrap_or(Chart {
+            seed,
+            chunks: HashMap::new(),
+        });
+}
+
+fn save_chart_system(save_path: Res<SavePath>, chart: Res<Chart>) {
+    if let Err(e) = save_path.save(CHART_FILE, &*chart) {
+        warn!("could not save the chart: {}", e);
+    }
+}
+
+fn autosave_system(
+    time: Res<Time>,
+    mut timer: Local<Option<Timer>>,
+    save_path: Res<SavePath>,
+    chart: Res<Chart>,
+) {
+    if timer.is_none() {
+        *timer = Some(Timer::new(AUTOSAVE_INTERVAL, true));
+    }
+    let timer = timer.as_mut().unwrap();
+    timer.tick(time.delta_seconds());
+    if timer.just_finished() {
+        save_chart_system(save_path, chart);
+    }
+}
+
+//Charts the cells around the ship when it moves to another cell, or when new islands are generated.
+//The islands may be generated after their cells were charted as sea, so only the land is final.
+fn explore_system(
+    mut chart: ResMut<Chart>,
+    mut last_reading: Local<Option<((i32, i32), usize)>>,
+    islands: Res<Islands>,
+    kdtree: Res<SeaCollisionTree>,
+    pos_update: Res<PlayerPositionUpdate>,
+) {
+    let center = to_cell(pos_update.translation).floor();
+    let center = (center.x as i32, center.y as i32);
+    let reading = Some((center, islands.0.len()));
+    if *last_reading == reading {
+        return;
+    }
+    *last_reading = reading;
+    let radius = SIGHT_RADIUS / CELL_SIZE;
+    for x in center.0 - radius..=center.0 + radius {
+        for y in center.1 - radius..=center.1 + radius {
+            if (x - center.0).pow(2) + (y - center.1).pow(2) > radius * radius
+                || chart.cell((x, y)) == Some(true)
+            {
+                continue;
+            }
+            let land = (0..CELL_SIZE).any(|i| {
+                (0..CELL_SIZE).any(|j| {
+                    kdtree.tile_at(&islands, x * CELL_SIZE + i, y * CELL_SIZE + j)
+                        != TileKind::Sea(false)
+                })
+            });
+            chart.explore((x, y), land);
+        }
+    }
+}
+
+//Paints the explored cells in `rect`, `center` being the cell at its center and `cell_size` the side of a cell in pixels
+fn paint_chart(
+    painter: &egui::Painter,
+    rect: egui::Rect,
+    chart: &Chart,
+    center: Vec2,
+    cell_size: f32,
+) {
+    painter.rect_filled(rect, 0., FOG_COLOR);
+    let half_cells = Vec2::new(rect.width(), rect.height()) / cell_size / 2.;
+    let (min, max) = (center - half_cells, center + half_cells);
+    let to_screen = |x: f32, y: f32| {
+        egui::pos2(
+            rect.center().x + (x - center.x) * cell_size,
+            //egui's y axis points down
+            rect.center().y - (y - center.y) * cell_size,
+        )
+    };
+    for (&(chunk_x, chunk_y), chunk) in chart.chunks.iter() {
+        let (origin_x, origin_y) = (chunk_x * CHUNK_SIZE, chunk_y * CHUNK_SIZE);
+        if (origin_x + CHUNK_SIZE) as f32 <= min.x
+            || origin_x as f32 >= max.x
+            || (origin_y + CHUNK_SIZE) as f32 <= min.y
+            || origin_y as f32 >= max.y
+        {
+            continue;
+        }
+        for row in 0..CHUNK_SIZE as usize {
+            let y = (origin_y + row as i32) as f32;
+            //the cells of a row are painted in runs of the same kind
+            let mut run_start = 0;
+            for x in 1..=CHUNK_SIZE {
+                let kind = |x: i32| {
+                    let bit = 1 << x;
+                    (chunk.explored[row] & bit != 0, chunk.land[row] & bit != 0)
+                };
+                if x < CHUNK_SIZE && kind(x) == kind(run_start) {
+                    continue;
+                }
+                let (explored, land) = kind(run_start);
+                if explored {
+                    let color = if land { LAND_COLOR } else { SEA_COLOR };
+                    let run = egui::Rect::from_two_pos(
+                        to_screen((origin_x + run_start) as f32, y + 1.),
+                        to_screen((origin_x + x) as f32, y),
+                    );
+                    painter.rect_filled(run, 0., color);
+                }
+                run_start = x;
+            }
+        }
+    }
+}
+
+//An arrow on the chart showing the ship and its heading
+fn paint_ship(painter: &egui::Painter, position: egui::Pos2, heading: f32) {
+    let direction = egui::vec2(heading.cos(), -heading.sin());
+    let stroke = egui::Stroke::new(2., egui::Color32::RED);
+    painter.circle_filled(position, 3., egui::Color32::RED);
+    painter.line_segment([position, position + direction * 10.], stroke);
+}
+
+fn minimap_system(
+    mut egui_context: ResMut<EguiContext>,
+    windows: Res<Windows>,
+    chart: Res<Chart>,
+    view: Res<ChartView>,
+    pos_update: Res<PlayerPositionUpdate>,
+    player_query: Query<&Player, Without<AiShip>>,
+) {
+    if view.open {
+        return;
+    }
+    let width = windows.get_primary().map_or(0., |window| window.width());
+    let ctx = &mut egui_context.ctx;
+    egui::Area::new("minimap")
+        .fixed_pos(egui::pos2(width - MINIMAP_SIZE - 4., 4.))
+        .show(ctx, |ui| {
+            let (rect, _) = ui
+                .allocate_exact_size(egui::vec2(MINIMAP_SIZE, MINIMAP_SIZE), egui::Sense::hover());
+            let painter = ui.painter_at(rect);
+            let center = to_cell(pos_update.translation);
+            paint_chart(&painter, rect, &chart, center, MINIMAP_CELL);
+            for player in player_query.iter() {
+                paint_ship(&painter, rect.center(), player.heading());
+            }
+            painter.rect_stroke(rect, 0., egui::Stroke::new(1., egui::Color32::BLACK));
+        });
+}
+
+//The full screen chart, opened with M
+pub struct ChartView {
+    pub open: bool,
+    //the cell at the center of the chart
+    center: Vec2,
+    //the side of a cell, in pixels
+    cell_size: f32,
+}
+impl Default for ChartView {
+    fn default() -> Self {
+        ChartView {
+            open: false,
+            center: Vec2::zero(),
+            cell_size: 4.,
+        }
+    }
+}
+
+fn chart_system(
+    mut egui_context: ResMut<EguiContext>,
+    keyboard_input: Res<Input<KeyCode>>,
+    windows: Res<Windows>,
+    chart: Res<Chart>,
+    mut view: ResMut<ChartView>,
+    pos_update: Res<PlayerPositionUpdate>,
+    player_query: Query<&Player, Without<AiShip>>,
+) {
+    let ship = to_cell(pos_update.translation);
+    if keyboard_input.just_pressed(KeyCode::M) {
+        view.open = !view.open;
+        view.center = ship;
+    }
+    if !view.open {
+        return;
+    }
+    let size = windows
+        .get_primary()
+        .map_or(egui::vec2(800., 600.), |window| {
+            egui::vec2(window.width(), window.height()) * 0.8
+        });
+    let ctx = &mut egui_context.ctx;
+    let mut open = true;
+    egui::Window::new("Chart")
+        .collapsible(false)
+        .resizable(false)
+        .open(&mut open)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("+").clicked() {
+                    view.cell_size = (view.cell_size * CHART_ZOOM_STEP).min(MAX_CHART_CELL);
+                }
+                if ui.button("-").clicked() {
+                    view.cell_size = (view.cell_size / CHART_ZOOM_STEP).max(MIN_CHART_CELL);
+                }
+                if ui.button("Center on the ship").clicked() {
+                    view.center = ship;
+                }
+            });
+            let (rect, response) = ui.allocate_exact_size(size, egui::Sense::drag());
+            //dragging pans, scrolling zooms
+            let drag = response.drag_delta();
+            view.center -= Vec2::new(drag.x, -drag.y) / view.cell_size;
+            if response.hovered() {
+                let scroll = ui.input().scroll_delta.y;
+                if scroll != 0. {
+                    view.cell_size = (view.cell_size * CHART_ZOOM_STEP.powf(scroll.signum()))
+                        .max(MIN_CHART_CELL)
+                        .min(MAX_CHART_CELL);
+                }
+            }
+            let painter = ui.painter_at(rect);
+            paint_chart(&painter, rect, &chart, view.center, view.cell_size);
+            let offset = (ship - view.center) * view.cell_size;
+            for player in player_query.iter() {
+                paint_ship(
+                    &painter,
+                    rect.center() + egui::vec2(offset.x, -offset.y),
+                    player.heading(),
+                );
+            }
+        });
+    if !open {
+        view.open = false;
+    }
+}
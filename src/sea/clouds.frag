@@ -0,0 +1,74 @@
+#version 450
+layout(location = 0) in vec4 world_position;
+layout(location = 0) out vec4 o_Target;
+layout(set = 2, binding = 0) uniform CloudLayer_offset {
+    vec2 offset;
+};
+layout(set = 2, binding = 1) uniform CloudLayer_scale {
+    float scale;
+};
+layout(set = 2, binding = 2) uniform CloudLayer_density {
+    float density;
+};
+layout(set = 2, binding = 3) uniform CloudLayer_opacity {
+    float opacity;
+};
+layout(set = 2, binding = 4) uniform CloudLayer_color {
+    vec4 color;
+};
+
+float hash(vec2 p) {
+    p = fract(p * vec2(123.34, 456.21));
+    p += dot(p, p + 45.32);
+    return fract(p.x * p.y);
+}
+
+// The noise repeats every NOISE_PERIOD cells, which must match CLOUD_NOISE_PERIOD,
+// so the drift can wrap without the clouds jumping
+const float NOISE_PERIOD = 256.;
+
+// Smooth value noise between 0 and 1, repeating every `period` cells
+float value_noise(vec2 p, float period) {
+    vec2 i = floor(p);
+    vec2 f = fract(p);
+    vec2 u = f * f * (3. - 2. * f);
+    vec2 i0 = mod(i, period);
+    vec2 i1 = mod(i + 1., period);
+    return mix(
+        mix(hash(i0), hash(vec2(i1.x, i0.y)), u.x),
+        mix(hash(vec2(i0.x, i1.y)), hash(i1), u.x),
+        u.y);
+}
+
+// Four octaves of noise, between 0 and 1. Each octave doubles the frequency and the period,
+// so the sum repeats every NOISE_PERIOD too.
+float fbm(vec2 p) {
+    float value = 0.;
+    float amplitude = 0.5;
+    float period = NOISE_PERIOD;
+    for (int i = 0; i < 4; i++) {
+        value += amplitude * value_noise(p, period);
+        p = p * 2. + vec2(17., 9.);
+        period *= 2.;
+        amplitude *= 0.5;
+    }
+    return value / 0.9375;
+}
+
+void main() {
+    if (density <= 0.) {
+        discard;
+    }
+    // the offset holds the drift with the wind and the parallax of the layer
+    vec2 p = (world_position.xy + offset) / scale;
+    float cloud = fbm(p);
+    // the denser the layer, the more of the noise is covered
+    float threshold = 1. - density;
+    float alpha = smoothstep(threshold, threshold + 0.25, cloud) * opacity;
+    if (alpha <= 0.001) {
+        discard;
+    }
+    // the thick parts of the clouds are a bit darker
+    float shade = 1. - 0.25 * smoothstep(threshold + 0.2, 1., cloud);
+    o_Target = vec4(color.rgb * shade, alpha);
+}
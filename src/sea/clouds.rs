@@ -0,0 +1,239 @@
+use std::f32::consts::PI;
+
+use bevy::{
+    prelude::*,
+    reflect::TypeUuid,
+    render::{
+        camera::Camera,
+        pipeline::{PipelineDescriptor, RenderPipeline},
+        render_graph::{
+            base::{self, MainPass},
+            RenderGraph, RenderResourcesNode,
+        },
+        renderer::RenderResources,
+        shader::{ShaderStage, ShaderStages},
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{clock::GameClock, loading::GameState};
+
+use super::{weather::Weather, wind::Wind};
+
+//Drifting clouds and mist over the sea. Each layer is a plane following the camera like the sea,
+//its shader drawing the clouds from the world position shifted by the drift and the parallax.
+pub struct SeaCloudsPlugin;
+impl Plugin for SeaCloudsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        {
+            let resources = app.resources_mut();
+            let mut render_graph = resources.get_mut::<RenderGraph>().unwrap();
+            render_graph.add_clouds_graph(resources);
+        }
+        app.insert_resource(read_clouds_config())
+            .on_state_enter(
+                GameState::STAGE,
+                GameState::Sea,
+                spawn_clouds_system.system(),
+            )
+            .on_state_update(GameState::STAGE, GameState::Sea, clouds_system.system())
+            .on_state_exit(
+                GameState::STAGE,
+                GameState::Sea,
+                unload_clouds_system.system(),
+            );
+    }
+}
+
+const VERTEX_SHADER: &str = include_str!("../background/sea_bg.vert");
+
+const FRAGMENT_SHADER: &str = include_str!("clouds.frag");
+
+pub const CLOUDS_PIPELINE_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(PipelineDescriptor::TYPE_UUID, 0x51e2b7c94d0a3f86);
+
+//the fog of a full storm, where the mist is the densest
+const DENSEST_FOG: f32 = 0.6;
+//the noise of the shader repeats every this many times the scale of the layer, the drift wraps
+//around it. It must match NOISE_PERIOD in clouds.frag.
+const CLOUD_NOISE_PERIOD: f32 = 256.;
+
+fn build_clouds_pipeline(shaders: &mut Assets<Shader>) -> PipelineDescriptor {
+    PipelineDescriptor::default_config(ShaderStages {
+        vertex: shaders.add(Shader::from_glsl(ShaderStage::Vertex, VERTEX_SHADER)),
+        fragment: Some(shaders.add(Shader::from_glsl(ShaderStage::Fragment, FRAGMENT_SHADER))),
+    })
+}
+
+pub trait CloudsRenderGraphBuilder {
+    fn add_clouds_graph(&mut self, resources: &Resources) -> &mut Self;
+}
+
+impl CloudsRenderGraphBuilder for RenderGraph {
+    fn add_clouds_graph(&mut self, resources: &Resources) -> &mut Self {
+        self.add_system_node("clouds", RenderResourcesNode::<CloudLayer>::new(true));
+        self.add_node_edge("clouds", base::node::MAIN_PASS).unwrap();
+        let mut pipelines = resources.get_mut::<Assets<PipelineDescriptor>>().unwrap();
+        let mut shaders = resources.get_mut::<Assets<Shader>>().unwrap();
+        pipelines.set_untracked(CLOUDS_PIPELINE_HANDLE, build_clouds_pipeline(&mut shaders));
+        self
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum CloudKind {
+    //follows the fog of the weather
+    Mist,
+    //follows the cloud cover of the weather
+    Cloud,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CloudLayerConfig {
+    pub kind: CloudKind,
+    pub z: f32,
+    //0 moves with the sea, higher moves faster than the sea when the camera pans
+    pub parallax: f32,
+    //the drift in pixels per second for a wind of 1
+    pub speed: f32,
+    //the size of the shapes, in pixels
+    pub scale: f32,
+    //the share of the sky covered under the densest weather
+    pub density: f32,
+    pub opacity: f32,
+    //rgb between 0 and 1
+    pub color: (f32, f32, f32),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CloudsConfig {
+    pub enabled: bool,
+    pub layers: Vec<CloudLayerConfig>,
+}
+
+fn read_clouds_config() -> CloudsConfig {
+    let clouds_config_string =
+        std::fs::read_to_string("config/clouds.ron").expect("clouds config file not found");
+    ron::from_str(&clouds_config_string).expect("syntax error on clouds config file")
+}
+
+//The uniforms of the clouds shader
+#[derive(Debug, Default, RenderResources, TypeUuid, Reflect)]
+#[uuid = "b6d1e0f3-2c47-4a85-9e3b-7f20c4d8a915"]
+pub struct CloudLayer {
+    pub offset: Vec2,
+    pub scale: f32,
+    pub density: f32,
+    pub opacity: f32,
+    pub color: Color,
+}
+
+//The state of a layer, `layer` being its index in the config
+struct CloudDrift {
+    layer: usize,
+    drift: Vec2,
+}
+
+#[derive(Bundle)]
+struct CloudLayerBundle {
+    draw: Draw,
+    visible: Visible,
+    render_pipelines: RenderPipelines,
+    main_pass: MainPass,
+    mesh: Handle<Mesh>,
+    transform: Transform,
+    global_transform: GlobalTransform,
+    cloud_layer: CloudLayer,
+    drift: CloudDrift,
+}
+
+fn spawn_clouds_system(
+    commands: &mut Commands,
+    config: Res<CloudsConfig>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    if !config.enabled {
+        return;
+    }
+    let mesh = meshes.add(Mesh::from(shape::Plane { size: 10000.0 }));
+    for (layer, layer_config) in config.layers.iter().enumerate() {
+        let mut transform = Transform::from_rotation(Quat::from_rotation_x(PI / 2.));
+        transform.translation.z = layer_config.z;
+        let (r, g, b) = layer_config.color;
+        commands.spawn(CloudLayerBundle {
+            draw: Default::default(),
+            visible: Visible {
+                is_visible: true,
+                is_transparent: true,
+            },
+            render_pipelines: RenderPipelines::from_pipelines(vec![RenderPipeline::new(
+                CLOUDS_PIPELINE_HANDLE.typed(),
+            )]),
+            main_pass: MainPass,
+            mesh: mesh.clone(),
+            transform,
+            global_transform: Default::default(),
+            cloud_layer: CloudLayer {
+                offset: Vec2::zero(),
+                scale: layer_config.scale,
+                density: 0.,
+                opacity: layer_config.opacity,
+                color: Color::rgb(r, g, b),
+            },
+            drift: CloudDrift {
+                layer,
+                drift: Vec2::zero(),
+            },
+        });
+    }
+}
+
+fn clouds_system(
+    time: Res<Time>,
+    config: Res<CloudsConfig>,
+    wind: Res<Wind>,
+    weather: Res<Weather>,
+    clock: Res<GameClock>,
+    camera_query: Query<&Transform, With<Camera>>,
+    mut layer_query: Query<(&mut CloudLayer, &mut CloudDrift, &mut Transform), Without<Camera>>,
+) {
+    let camera_transform = match camera_query.iter().next() {
+        Some(camera_transform) => *camera_transform,
+        None => return,
+    };
+    let camera = camera_transform.translation.truncate();
+    let wind = wind.at(camera);
+    let tint = clock.tint();
+    for (mut cloud_layer, mut drift, mut transform) in layer_query.iter_mut() {
+        let layer_config = match config.layers.get(drift.layer) {
+            Some(layer_config) => layer_config,
+            None => continue,
+        };
+        transform.translation.x = camera.x;
+        transform.translation.y = camera.y;
+        //the plane keeps covering the screen when zoomed out
+        transform.scale = camera_transform.scale;
+
+        //the clouds go where the wind blows, so they are sampled from upwind
+        drift.drift -= wind * layer_config.speed * time.delta_seconds();
+        let period = CLOUD_NOISE_PERIOD * layer_config.scale;
+        drift.drift = Vec2::new(
+            drift.drift.x.rem_euclid(period),
+            drift.drift.y.rem_euclid(period),
+        );
+        cloud_layer.offset = drift.drift + camera * layer_config.parallax;
+        let cover = match layer_config.kind {
+            CloudKind::Mist => (weather.current.fog / DENSEST_FOG).min(1.),
+            CloudKind::Cloud => weather.current.clouds,
+        };
+        cloud_layer.density = layer_config.density * cover;
+        let (r, g, b) = layer_config.color;
+        cloud_layer.color = Color::rgb(r * tint.r(), g * tint.g(), b * tint.b());
+    }
+}
+
+fn unload_clouds_system(commands: &mut Commands, layer_query: Query<Entity, With<CloudDrift>>) {
+    for entity in layer_query.iter() {
+        commands.despawn(entity);
+    }
+}
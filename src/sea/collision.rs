@@ -1,17 +1,44 @@
 use crate::loading::GameState;
-use bevy::prelude::*;
+use bevy::{
+    prelude::*,
+    render::camera::{Camera, OrthographicProjection},
+};
 use kdtree_collisions::KdValue;
-use parry2d::{math::Isometry, na::Vector2};
+use parry2d::{
+    math::{Isometry, Vector},
+    na::{Unit, Vector2},
+    shape::TriMesh,
+};
 
 use super::{
+    camera::SeaCamera,
     loader::SeaHandles,
-    map::Islands,
+    map::{Islands, TileKind},
     player::{CollisionType, PlayerPositionUpdate},
     worldgen::IslandQueue,
     ISLAND_SCALING, TILE_SIZE,
 };
 #[derive(Debug, Default)]
 pub struct SeaCollisionTree(pub kdtree_collisions::KdTree<IslandValue, 16>);
+impl SeaCollisionTree {
+    //The kind of the tile at a tile position, open sea outside of the islands
+    pub fn tile_at(&self, islands: &Islands, x: i32, y: i32) -> TileKind {
+        for value in self.0.query_rect(x, x, y, y) {
+            let island = &islands.0[value.island_id as usize];
+            let tile = island
+                .tiles
+                .get((x - island.min_x) as usize)
+                .map(|column| column.get((y - island.min_y) as usize))
+                .flatten();
+            if let Some(tile) = tile {
+                if tile.kind != TileKind::Sea(false) {
+                    return tile.kind;
+                }
+            }
+        }
+        TileKind::Sea(false)
+    }
+}
 pub struct SeaCollisionPlugin;
 impl Plugin for SeaCollisionPlugin {
     fn build(&self, app: &mut AppBuilder) {
@@ -21,7 +48,13 @@ impl Plugin for SeaCollisionPlugin {
                 GameState::Sea,
                 add_islands_system.system(),
             )
+            .on_state_update(
+                GameState::STAGE,
+                GameState::Sea,
+                streaming_area_system.system(),
+            )
             .add_event::<IslandSpawnEvent>()
+            .init_resource::<StreamingArea>()
             .init_resource::<SeaCollisionTree>();
     }
 }
@@ -54,88 +87,164 @@ impl KdValue for IslandValue {
     }
 }
 
+//half size, in tiles, of the rectangle queried around the player for contacts
+pub const CONTACT_DISTANCE: i32 = 2;
+//margins, in tiles, added around the visible rectangle
+const SPAWN_MARGIN: i32 = 8;
+const DESPAWN_HYSTERESIS: i32 = 6;
+const GENERATION_MARGIN: i32 = 16;
+
+//The areas around the player where islands are streamed, in tiles.
+//They follow the free camera instead while it is panned away.
+//Islands are spawned when they enter the spawn rectangle, but only despawned once they leave the larger despawn rectangle,
+//so islands at the edge do not flicker in and out.
+//They are derived from the visible rectangle of the camera by streaming_area_system.
+pub struct StreamingArea {
+    //the tile the areas are centered on
+    pub center: (i32, i32),
+    pub spawn_distance: (i32, i32),
+    pub despawn_distance: (i32, i32),
+    pub generation_distance: i32,
+}
+impl Default for StreamingArea {
+    fn default() -> Self {
+        StreamingArea {
+            center: (0, 0),
+            spawn_distance: (100, 50),
+            despawn_distance: (100 + DESPAWN_HYSTERESIS, 50 + DESPAWN_HYSTERESIS),
+            generation_distance: 50,
+        }
+    }
+}
+
+fn streaming_area_system(
+    windows: Res<Windows>,
+    sea_camera: Res<SeaCamera>,
+    pos_update: Res<PlayerPositionUpdate>,
+    mut area: ResMut<StreamingArea>,
+    camera_query: Query<(&OrthographicProjection, &Transform), With<Camera>>,
+) {
+    area.center = (pos_update.x, pos_update.y);
+    for (projection, transform) in camera_query.iter() {
+        if sea_camera.free {
+            let tile = transform.translation / (TILE_SIZE as f32 * ISLAND_SCALING);
+            area.center = (tile.x as i32, tile.y as i32);
+        }
+        let mut half_size = Vec2::new(
+            projection.right - projection.left,
+            projection.top - projection.bottom,
+        ) / 2.;
+        //the projection is only set on the first window resize event
+        if half_size.x <= 0. || half_size.y <= 0. {
+            if let Some(window) = windows.get_primary() {
+                half_size = Vec2::new(window.width(), window.height()) / 2.;
+            }
+        }
+        let half_tiles =
+            (half_size * transform.scale.truncate() / (TILE_SIZE as f32 * ISLAND_SCALING)).ceil();
+        let (half_x, half_y) = (half_tiles.x as i32, half_tiles.y as i32);
+        area.spawn_distance = (half_x + SPAWN_MARGIN, half_y + SPAWN_MARGIN);
+        area.despawn_distance = (
+            area.spawn_distance.0 + DESPAWN_HYSTERESIS,
+            area.spawn_distance.1 + DESPAWN_HYSTERESIS,
+        );
+        area.generation_distance = half_x.max(half_y) + GENERATION_MARGIN;
+    }
+}
+
 pub struct IslandSpawnEvent(pub u32);
 fn collision_system(
     mut spawn_events: ResMut<Events<IslandSpawnEvent>>,
     mut player_pos_update: ResMut<PlayerPositionUpdate>,
+    area: Res<StreamingArea>,
     handles: Res<SeaHandles>,
     islands: Res<Islands>,
     kdtree: Res<SeaCollisionTree>,
 ) {
-    for island_to_spawn in kdtree.0.query_rect(
-        player_pos_update.x - 100,
-        player_pos_update.x + 100,
-        player_pos_update.y - 50,
-        player_pos_update.y + 50,
-    ) {
-        spawn_events.send(IslandSpawnEvent(island_to_spawn.island_id))
+    let (x, y) = area.center;
+    let (spawn_x, spawn_y) = area.spawn_distance;
+    let (despawn_x, despawn_y) = area.despawn_distance;
+    for island_to_spawn in
+        kdtree
+            .0
+            .query_rect(x - despawn_x, x + despawn_x, y - despawn_y, y + despawn_y)
+    {
+        //between the two rectangles, islands are only kept if they are already spawned
+        let in_spawn_area = island_to_spawn.max_x >= x - spawn_x
+            && island_to_spawn.min_x <= x + spawn_x
+            && island_to_spawn.max_y >= y - spawn_y
+            && island_to_spawn.min_y <= y + spawn_y;
+        if in_spawn_area
+            || islands.0[island_to_spawn.island_id as usize]
+                .entity
+                .is_some()
+        {
+            spawn_events.send(IslandSpawnEvent(island_to_spawn.island_id))
+        }
     }
     player_pos_update.collision_status = CollisionType::None;
     player_pos_update.island_id = None;
+    //the hull meshes are built once the collision sheets are loaded
+    let hull = match handles
+        .ships
+        .get(player_pos_update.ship_class)
+        .map(|ship| ship.meshes.get(player_pos_update.sprite_id as usize))
+        .flatten()
+    {
+        Some(hull) => hull,
+        None => return,
+    };
+    let (collision_status, contact) =
+        hull_contact(&islands, &kdtree, hull, player_pos_update.translation);
+    player_pos_update.collision_status = collision_status;
+    if let Some((island_id, contact)) = contact {
+        player_pos_update.island_id = Some(island_id);
+        player_pos_update.contact = Some(contact);
+    }
+}
+
+//a contact point relative to the island, and the contact normal
+pub type Contact = (f32, f32, Unit<Vector<f32>>);
+
+//The contact of a ship hull at `translation` with the islands around it, and the island touched.
+//A rigid contact takes precedence over a friction one.
+pub fn hull_contact(
+    islands: &Islands,
+    kdtree: &SeaCollisionTree,
+    hull: &TriMesh,
+    translation: Vec3,
+) -> (CollisionType, Option<(u32, Contact)>) {
+    let x = (translation.x / TILE_SIZE as f32 / ISLAND_SCALING) as i32;
+    let y = (translation.y / TILE_SIZE as f32 / ISLAND_SCALING) as i32;
+    let ship_position = Isometry::new(
+        Vector2::new(
+            translation.x / ISLAND_SCALING,
+            translation.y / ISLAND_SCALING,
+        ),
+        0.,
+    );
+    let mut result = (CollisionType::None, None);
     for close_island in kdtree.0.query_rect(
-        player_pos_update.x - 2,
-        player_pos_update.x + 2,
-        player_pos_update.y - 2,
-        player_pos_update.y + 2,
+        x - CONTACT_DISTANCE,
+        x + CONTACT_DISTANCE,
+        y - CONTACT_DISTANCE,
+        y + CONTACT_DISTANCE,
     ) {
         let island = &islands.0[close_island.island_id as usize];
-        let intersect_rigid = if let Some(rigid_mesh) = &island.rigid_trimesh {
-            parry2d::query::contact(
-                &Isometry::new(
-                    Vector2::new(
-                        (island.min_x * TILE_SIZE) as f32,
-                        (island.min_y * TILE_SIZE) as f32,
-                    ),
-                    0.,
-                ),
-                rigid_mesh,
-                &Isometry::new(
-                    Vector2::new(
-                        player_pos_update.translation.x / ISLAND_SCALING,
-                        player_pos_update.translation.y / ISLAND_SCALING,
-                    ),
-                    0.,
-                ),
-                &handles.boat_meshes[player_pos_update.sprite_id as usize],
-                0.,
-            )
-            .unwrap_or(None)
-            .map(|c| {
-                (
-                    c.point1.x - (island.min_x * TILE_SIZE) as f32,
-                    c.point1.y - (island.min_y * TILE_SIZE) as f32,
-                    c.normal1,
-                )
-            })
-        } else {
-            None
-        };
-        if intersect_rigid.is_some() {
-            player_pos_update.collision_status = CollisionType::Rigid;
-            player_pos_update.island_id = Some(close_island.island_id);
-            player_pos_update.contact = intersect_rigid;
-        } else {
-            let intersect_friction = if let Some(friction_mesh) = &island.friction_trimesh {
-                parry2d::query::contact(
-                    &Isometry::new(
-                        Vector2::new(
-                            (island.min_x * TILE_SIZE) as f32,
-                            (island.min_y * TILE_SIZE) as f32,
-                        ),
-                        0.,
-                    ),
-                    friction_mesh,
-                    &Isometry::new(
-                        Vector2::new(
-                            player_pos_update.translation.x / ISLAND_SCALING,
-                            player_pos_update.translation.y / ISLAND_SCALING,
-                        ),
-                        0.,
-                    ),
-                    &handles.boat_meshes[player_pos_update.sprite_id as usize],
-                    0.,
-                )
-                .unwrap_or(None)
+        let island_position = Isometry::new(
+            Vector2::new(
+                (island.min_x * TILE_SIZE) as f32,
+                (island.min_y * TILE_SIZE) as f32,
+            ),
+            0.,
+        );
+        let contact_with = |mesh: &Option<TriMesh>| {
+            mesh.as_ref()
+                .map(|mesh| {
+                    parry2d::query::contact(&island_position, mesh, &ship_position, hull, 0.)
+                        .unwrap_or(None)
+                })
+                .flatten()
                 .map(|c| {
                     (
                         c.point1.x - (island.min_x * TILE_SIZE) as f32,
@@ -143,16 +252,21 @@ fn collision_system(
                         c.normal1,
                     )
                 })
-            } else {
-                None
-            };
-            if intersect_friction.is_some() {
-                player_pos_update.collision_status = CollisionType::Friction;
-                player_pos_update.island_id = Some(close_island.island_id);
-                player_pos_update.contact = intersect_friction;
-            }
+        };
+        if let Some(contact) = contact_with(&island.rigid_trimesh) {
+            return (
+                CollisionType::Rigid,
+                Some((close_island.island_id, contact)),
+            );
+        }
+        if let Some(contact) = contact_with(&island.friction_trimesh) {
+            result = (
+                CollisionType::Friction,
+                Some((close_island.island_id, contact)),
+            );
         }
     }
+    result
 }
 
 fn add_islands_system(
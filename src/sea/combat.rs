@@ -0,0 +1,274 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+
+use crate::{clock::Tinted, loading::GameState};
+
+use super::{
+    ai::AiShip,
+    collision::SeaCollisionTree,
+    hull::{DamageSource, Hull, ShipDamageEvent, Shipwreck},
+    loader::SeaHandles,
+    map::{Islands, TileKind},
+    player::Player,
+    ISLAND_SCALING, TILE_SIZE,
+};
+
+pub struct SeaCombatPlugin;
+impl Plugin for SeaCombatPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<ProjectileEvent>()
+            .on_state_update(GameState::STAGE, GameState::Sea, reload_system.system())
+            .on_state_update(GameState::STAGE, GameState::Sea, fire_input_system.system())
+            .on_state_update(GameState::STAGE, GameState::Sea, projectile_system.system())
+            .on_state_exit(
+                GameState::STAGE,
+                GameState::Sea,
+                unload_projectiles_system.system(),
+            );
+    }
+}
+
+const RELOAD_TIME: f32 = 4.;
+const PROJECTILE_SPEED: f32 = 400.;
+//the distance a projectile flies before falling in the water, in pixels
+const PROJECTILE_RANGE: f32 = 600.;
+const PROJECTILE_DAMAGE: f32 = 8.;
+const PROJECTILE_Z: f32 = 90.;
+//the cannons of one side are spread over this angle on both sides of the beam
+const FIRING_ARC: f32 = PI / 12.;
+//the distance between two cannons along the hull, in pixels
+const CANNON_SPACING: f32 = 8.;
+//a projectile closer than this to a ship hits it
+const HIT_RADIUS: f32 = 30.;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Broadside {
+    Port,
+    Starboard,
+}
+impl Broadside {
+    //The direction the cannons of this side fire to, for a ship heading at `heading`
+    pub fn direction(self, heading: f32) -> f32 {
+        match self {
+            Broadside::Port => heading + PI / 2.,
+            Broadside::Starboard => heading - PI / 2.,
+        }
+    }
+
+    //The side whose firing arc contains a target, `offset` being the target position relative to the ship
+    pub fn facing(heading: f32, offset: Vec2) -> Option<Broadside> {
+        let angle = f32::atan2(offset.y, offset.x);
+        [Broadside::Port, Broadside::Starboard]
+            .iter()
+            .copied()
+            .find(|side| {
+                let difference = (angle - side.direction(heading) + PI).rem_euclid(2. * PI) - PI;
+                difference.abs() <= FIRING_ARC
+            })
+    }
+}
+
+//The broadside cannons of a ship, each side reloads on its own
+pub struct Cannons {
+    //cannons on each side
+    pub count: u32,
+    port_reload: Timer,
+    starboard_reload: Timer,
+}
+impl Cannons {
+    //Cannons ready to fire
+    pub fn new(count: u32) -> Self {
+        let mut reload = Timer::from_seconds(RELOAD_TIME, false);
+        reload.tick(RELOAD_TIME);
+        Cannons {
+            count,
+            port_reload: reload.clone(),
+            starboard_reload: reload,
+        }
+    }
+
+    fn reload(&self, side: Broadside) -> &Timer {
+        match side {
+            Broadside::Port => &self.port_reload,
+            Broadside::Starboard => &self.starboard_reload,
+        }
+    }
+
+    pub fn ready(&self, side: Broadside) -> bool {
+        self.count > 0 && self.reload(side).finished()
+    }
+
+    //The share of the reload done, 1 when ready
+    pub fn reload_progress(&self, side: Broadside) -> f32 {
+        self.reload(side).percent()
+    }
+
+    //Fires a side if it is loaded, from the ship `owner` at `position`. The projectiles keep the speed of the ship.
+    //Returns true if the cannons fired.
+    pub fn fire(
+        &mut self,
+        commands: &mut Commands,
+        handles: &SeaHandles,
+        side: Broadside,
+        owner: Entity,
+        position: Vec2,
+        ship: &Player,
+    ) -> bool {
+        if !self.ready(side) {
+            return false;
+        }
+        match side {
+            Broadside::Port => self.port_reload.reset(),
+            Broadside::Starboard => self.starboard_reload.reset(),
+        }
+        let (heading, speed) = (ship.heading(), ship.speed());
+        let (s, c) = f32::sin_cos(heading);
+        let forward = Vec2::new(c, s);
+        let direction = side.direction(heading);
+        for i in 0..self.count {
+            //from -1 at the stern to 1 at the bow
+            let spread = if self.count > 1 {
+                2. * i as f32 / (self.count - 1) as f32 - 1.
+            } else {
+                0.
+            };
+            let angle = direction + spread * FIRING_ARC;
+            let (s, c) = f32::sin_cos(angle);
+            let start = position
+                + forward * spread * CANNON_SPACING * (self.count - 1) as f32 / 2.
+                + Vec2::new(c, s) * HIT_RADIUS;
+            commands
+                .spawn(SpriteBundle {
+                    material: handles.cannonball.clone(),
+                    transform: Transform::from_translation(start.extend(PROJECTILE_Z)),
+                    ..Default::default()
+                })
+                .with(Tinted(Color::WHITE))
+                .with(Projectile {
+                    owner,
+                    velocity: Vec2::new(c, s) * PROJECTILE_SPEED + forward * speed,
+                    range: PROJECTILE_RANGE,
+                });
+        }
+        true
+    }
+}
+
+struct Projectile {
+    owner: Entity,
+    velocity: Vec2,
+    //the distance left before falling in the water
+    range: f32,
+}
+
+//What happened to a projectile, for the effects
+pub enum ProjectileEvent {
+    //it fell in the water
+    Splash {
+        position: Vec2,
+    },
+    //it hit the coast of an island
+    Shore {
+        position: Vec2,
+    },
+    //it hit a ship
+    Hit {
+        position: Vec2,
+        ship: Entity,
+        owner: Entity,
+    },
+}
+
+fn reload_system(time: Res<Time>, mut cannons_query: Query<&mut Cannons>) {
+    for mut cannons in cannons_query.iter_mut() {
+        cannons.port_reload.tick(time.delta_seconds());
+        cannons.starboard_reload.tick(time.delta_seconds());
+    }
+}
+
+//Z fires to port, X to starboard
+fn fire_input_system(
+    commands: &mut Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    shipwreck: Res<Shipwreck>,
+    handles: Res<SeaHandles>,
+    mut player_query: Query<(Entity, &Player, &Transform, &mut Cannons), Without<AiShip>>,
+) {
+    if shipwreck.sunk {
+        return;
+    }
+    let side = if keyboard_input.just_pressed(KeyCode::Z) {
+        Broadside::Port
+    } else if keyboard_input.just_pressed(KeyCode::X) {
+        Broadside::Starboard
+    } else {
+        return;
+    };
+    for (entity, player, transform, mut cannons) in player_query.iter_mut() {
+        cannons.fire(
+            commands,
+            &handles,
+            side,
+            entity,
+            transform.translation.truncate(),
+            player,
+        );
+    }
+}
+
+fn projectile_system(
+    commands: &mut Commands,
+    time: Res<Time>,
+    kdtree: Res<SeaCollisionTree>,
+    islands: Res<Islands>,
+    mut projectile_events: ResMut<Events<ProjectileEvent>>,
+    mut damage_events: ResMut<Events<ShipDamageEvent>>,
+    mut projectile_query: Query<(Entity, &mut Projectile, &mut Transform)>,
+    ship_query: Query<(Entity, &Transform, &Hull), Without<Projectile>>,
+) {
+    for (entity, mut projectile, mut transform) in projectile_query.iter_mut() {
+        let step = projectile.velocity * time.delta_seconds();
+        transform.translation += step.extend(0.);
+        projectile.range -= step.length();
+        let position = transform.translation.truncate();
+
+        let hit = ship_query.iter().find(|(ship, ship_transform, hull)| {
+            *ship != projectile.owner
+                && hull.health > 0.
+                && ship_transform.translation.truncate().distance(position) < HIT_RADIUS
+        });
+        if let Some((ship, _, _)) = hit {
+            damage_events.send(ShipDamageEvent {
+                entity: ship,
+                amount: PROJECTILE_DAMAGE,
+                source: DamageSource::Cannon(projectile.owner),
+            });
+            projectile_events.send(ProjectileEvent::Hit {
+                position,
+                ship,
+                owner: projectile.owner,
+            });
+            commands.despawn(entity);
+            continue;
+        }
+
+        let tile = (position / (TILE_SIZE as f32 * ISLAND_SCALING)).floor();
+        if kdtree.tile_at(&islands, tile.x as i32, tile.y as i32) != TileKind::Sea(false) {
+            projectile_events.send(ProjectileEvent::Shore { position });
+            commands.despawn(entity);
+        } else if projectile.range <= 0. {
+            projectile_events.send(ProjectileEvent::Splash { position });
+            commands.despawn(entity);
+        }
+    }
+}
+
+fn unload_projectiles_system(
+    commands: &mut Commands,
+    projectile_query: Query<Entity, With<Projectile>>,
+) {
+    for entity in projectile_query.iter() {
+        commands.despawn(entity);
+    }
+}
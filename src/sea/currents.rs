@@ -0,0 +1,217 @@
+use std::{f32::consts::PI, hash::Hasher};
+
+use bevy::{prelude::*, render::camera::Camera};
+use noise::{NoiseFn, Perlin, Seedable};
+use seahash::SeaHasher;
+
+use crate::{
+    loading::GameState,
+    util::{next_random, SeededHasher},
+};
+
+use super::{collision::SeaCollisionTree, ISLAND_SCALING, TILE_SIZE};
+
+pub struct SeaCurrentsPlugin;
+impl Plugin for SeaCurrentsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Currents>()
+            .init_resource::<StreakMaterials>()
+            .on_state_enter(
+                GameState::STAGE,
+                GameState::Sea,
+                seed_currents_system.system(),
+            )
+            .on_state_update(
+                GameState::STAGE,
+                GameState::Sea,
+                current_streaks_system.system(),
+            )
+            .on_state_exit(
+                GameState::STAGE,
+                GameState::Sea,
+                unload_streaks_system.system(),
+            );
+    }
+}
+
+//the size, in pixels, of the current swirls
+const CURRENT_SCALE: f64 = 4000.;
+//the typical speed of the currents, in pixels per second
+const CURRENT_SPEED: f32 = 15.;
+//currents squeezed between islands on both sides are faster
+const CHANNEL_WIDTH: i32 = 24;
+const CHANNEL_SEARCH: i32 = 4;
+const CHANNEL_BOOST: f32 = 2.5;
+
+//The ocean currents. The field is deterministic : it only depends on the world seed and on the islands.
+//It uses its own noise, so it doesn't follow the island heights.
+pub struct Currents {
+    noise: Perlin,
+}
+impl Default for Currents {
+    fn default() -> Self {
+        Currents {
+            noise: Perlin::new(),
+        }
+    }
+}
+impl Currents {
+    //The current at a world position, in pixels per second.
+    pub fn at(&self, pos: Vec2, kdtree: &SeaCollisionTree) -> Vec2 {
+        let current = self.open_sea(pos);
+        if current.length_squared() == 0. {
+            return current;
+        }
+        //looks for land on both sides of the current
+        let tile = pos / (TILE_SIZE as f32 * ISLAND_SCALING);
+        let side = current.normalize().perp() * (CHANNEL_WIDTH / 2) as f32;
+        let has_land = |center: Vec2| {
+            let (x, y) = (center.x as i32, center.y as i32);
+            kdtree
+                .0
+                .query_rect(
+                    x - CHANNEL_SEARCH,
+                    x + CHANNEL_SEARCH,
+                    y - CHANNEL_SEARCH,
+                    y + CHANNEL_SEARCH,
+                )
+                .next()
+                .is_some()
+        };
+        if has_land(tile + side) && has_land(tile - side) {
+            current * CHANNEL_BOOST
+        } else {
+            current
+        }
+    }
+
+    //The current far from the islands.
+    //It is the curl of a noise, so the water never piles up or vanishes somewhere.
+    fn open_sea(&self, pos: Vec2) -> Vec2 {
+        const EPSILON: f64 = 0.01;
+        let x = pos.x as f64 / CURRENT_SCALE;
+        let y = pos.y as f64 / CURRENT_SCALE;
+        let d_dx =
+            (self.noise.get([x + EPSILON, y]) - self.noise.get([x - EPSILON, y])) / (2. * EPSILON);
+        let d_dy =
+            (self.noise.get([x, y + EPSILON]) - self.noise.get([x, y - EPSILON])) / (2. * EPSILON);
+        Vec2::new(d_dy as f32, -d_dx as f32) * CURRENT_SPEED
+    }
+}
+
+fn seed_currents_system(mut currents: ResMut<Currents>, hasher: Res<SeededHasher>) {
+    let mut hasher: SeaHasher = hasher.get_hasher();
+    hasher.write(b"sea_currents");
+    currents.noise = Perlin::new().set_seed(hasher.finish() as u32);
+}
+
+//The streaks drawn on the water to show the currents
+struct CurrentStreak {
+    age: f32,
+    lifetime: f32,
+}
+
+const STREAKS_PER_SECOND: f32 = 12.;
+const STREAK_LIFETIME: f32 = 3.;
+const STREAK_ALPHA: f32 = 0.25;
+//the streaks move faster than the water so the flow can be seen
+const STREAK_SPEEDUP: f32 = 4.;
+//the streaks fade through this many materials, shared by all of them
+const STREAK_FADE_STEPS: usize = 16;
+
+//The materials of the streaks, from transparent to STREAK_ALPHA
+struct StreakMaterials(Vec<Handle<ColorMaterial>>);
+impl FromResources for StreakMaterials {
+    fn from_resources(resources: &Resources) -> Self {
+        let mut materials = resources.get_mut::<Assets<ColorMaterial>>().unwrap();
+        StreakMaterials(
+            (0..STREAK_FADE_STEPS)
+                .map(|step| {
+                    let alpha = STREAK_ALPHA * step as f32 / (STREAK_FADE_STEPS - 1) as f32;
+                    materials.add(Color::rgba(1., 1., 1., alpha).into())
+                })
+                .collect(),
+        )
+    }
+}
+impl StreakMaterials {
+    fn with_alpha(&self, alpha: f32) -> &Handle<ColorMaterial> {
+        let step = (alpha / STREAK_ALPHA * (STREAK_FADE_STEPS - 1) as f32).round() as usize;
+        &self.0[step.min(STREAK_FADE_STEPS - 1)]
+    }
+}
+
+fn current_streaks_system(
+    commands: &mut Commands,
+    time: Res<Time>,
+    windows: Res<Windows>,
+    currents: Res<Currents>,
+    kdtree: Res<SeaCollisionTree>,
+    mut random_counter: Local<u64>,
+    mut to_spawn: Local<f32>,
+    streak_materials: Res<StreakMaterials>,
+    camera_query: Query<&Transform, With<Camera>>,
+    mut streak_query: Query<(
+        Entity,
+        &mut CurrentStreak,
+        &mut Transform,
+        &mut Handle<ColorMaterial>,
+    )>,
+) {
+    let delta = time.delta_seconds();
+    for (entity, mut streak, mut transform, mut material) in streak_query.iter_mut() {
+        streak.age += delta;
+        if streak.age >= streak.lifetime {
+            commands.despawn(entity);
+            continue;
+        }
+        let current = currents.at(transform.translation.truncate(), &kdtree);
+        transform.translation += (current * STREAK_SPEEDUP * delta).extend(0.);
+        transform.rotation = Quat::from_rotation_z(f32::atan2(current.y, current.x));
+        //fades in and out, and stronger currents are more visible
+        let alpha = (PI * streak.age / streak.lifetime).sin()
+            * STREAK_ALPHA
+            * (current.length() / (CURRENT_SPEED * CHANNEL_BOOST)).min(1.);
+        let faded = streak_materials.with_alpha(alpha);
+        if *material != *faded {
+            *material = faded.clone();
+        }
+    }
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    *to_spawn += STREAKS_PER_SECOND * delta;
+    for camera_transform in camera_query.iter() {
+        let half_size =
+            Vec2::new(window.width(), window.height()) * camera_transform.scale.truncate() / 2.;
+        while *to_spawn >= 1. {
+            *to_spawn -= 1.;
+            let offset = Vec2::new(
+                next_random(&mut random_counter) * 2. - 1.,
+                next_random(&mut random_counter) * 2. - 1.,
+            ) * half_size;
+            let position = camera_transform.translation.truncate() + offset;
+            commands
+                .spawn(SpriteBundle {
+                    material: streak_materials.with_alpha(0.).clone(),
+                    sprite: Sprite::new(Vec2::new(24., 2.)),
+                    transform: Transform::from_translation(position.extend(1.)),
+                    ..Default::default()
+                })
+                .with(CurrentStreak {
+                    age: 0.,
+                    lifetime: STREAK_LIFETIME * (0.5 + next_random(&mut random_counter)),
+                });
+        }
+    }
+}
+
+fn unload_streaks_system(
+    commands: &mut Commands,
+    streak_query: Query<Entity, With<CurrentStreak>>,
+) {
+    for entity in streak_query.iter() {
+        commands.despawn(entity);
+    }
+}
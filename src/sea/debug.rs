@@ -0,0 +1,268 @@
+use bevy::{prelude::*, render::pipeline::PrimitiveTopology, utils::HashMap};
+use parry2d::shape::TriMesh;
+
+use crate::loading::GameState;
+
+use super::{
+    collision::{StreamingArea, CONTACT_DISTANCE},
+    loader::SeaHandles,
+    map::{IslandBundle, Islands},
+    player::PlayerPositionUpdate,
+    ISLAND_SCALING, TILE_SIZE,
+};
+
+//Draws the collision meshes as wireframes. Toggled with F3.
+pub struct SeaDebugPlugin;
+impl Plugin for SeaDebugPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<CollisionDebug>()
+            .add_system(toggle_debug_system.system())
+            .on_state_update(
+                GameState::STAGE,
+                GameState::Sea,
+                collision_debug_system.system(),
+            )
+            .on_state_exit(
+                GameState::STAGE,
+                GameState::Sea,
+                unload_debug_system.system(),
+            );
+    }
+}
+
+#[derive(Default)]
+pub struct CollisionDebug {
+    pub enabled: bool,
+}
+
+struct CollisionDebugFlag;
+
+const DEBUG_Z: f32 = 150.;
+const NORMAL_LENGTH: f32 = 20.;
+
+struct DebugMaterials {
+    rigid: Handle<ColorMaterial>,
+    friction: Handle<ColorMaterial>,
+    hull: Handle<ColorMaterial>,
+    contact: Handle<ColorMaterial>,
+    spawn_rect: Handle<ColorMaterial>,
+    despawn_rect: Handle<ColorMaterial>,
+    contact_rect: Handle<ColorMaterial>,
+}
+impl FromResources for DebugMaterials {
+    fn from_resources(resources: &Resources) -> Self {
+        let mut materials = resources.get_mut::<Assets<ColorMaterial>>().unwrap();
+        Self {
+            rigid: materials.add(Color::RED.into()),
+            friction: materials.add(Color::YELLOW.into()),
+            hull: materials.add(Color::GREEN.into()),
+            contact: materials.add(Color::FUCHSIA.into()),
+            spawn_rect: materials.add(Color::BLUE.into()),
+            despawn_rect: materials.add(Color::NAVY.into()),
+            contact_rect: materials.add(Color::CYAN.into()),
+        }
+    }
+}
+
+fn toggle_debug_system(keyboard_input: Res<Input<KeyCode>>, mut debug: ResMut<CollisionDebug>) {
+    if keyboard_input.just_pressed(KeyCode::F3) {
+        debug.enabled = !debug.enabled;
+    }
+}
+
+//Builds a mesh made of independent segments, drawn by the sprite pipeline.
+fn lines_to_mesh(lines: &[(Vec2, Vec2)]) -> Mesh {
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    for (start, end) in lines {
+        positions.push([start.x, start.y, 0.]);
+        positions.push([end.x, end.y, 0.]);
+        normals.push([0., 0., 1.]);
+        normals.push([0., 0., 1.]);
+        uvs.push([0., 0.]);
+        uvs.push([0., 0.]);
+    }
+    let indices = (0..positions.len() as u32).collect();
+    let mut mesh = Mesh::new(PrimitiveTopology::LineList);
+    mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.set_indices(Some(bevy::render::mesh::Indices::U32(indices)));
+    mesh
+}
+
+fn trimesh_to_lines(trimesh: &TriMesh, scale: f32, offset: Vec2) -> Vec<(Vec2, Vec2)> {
+    let vertices = trimesh.vertices();
+    let point = |i: u32| Vec2::new(vertices[i as usize].x, vertices[i as usize].y) * scale + offset;
+    trimesh
+        .indices()
+        .iter()
+        .map(|[a, b, c]| {
+            vec![
+                (point(*a), point(*b)),
+                (point(*b), point(*c)),
+                (point(*c), point(*a)),
+            ]
+        })
+        .flatten()
+        .collect()
+}
+
+//the rectangle, in world coordinates, covered by the tiles from min to max (inclusive)
+fn tile_rect_to_lines(min: (i32, i32), max: (i32, i32)) -> Vec<(Vec2, Vec2)> {
+    let tile = TILE_SIZE as f32 * ISLAND_SCALING;
+    let (x0, y0) = (min.0 as f32 * tile, min.1 as f32 * tile);
+    let (x1, y1) = ((max.0 + 1) as f32 * tile, (max.1 + 1) as f32 * tile);
+    vec![
+        (Vec2::new(x0, y0), Vec2::new(x1, y0)),
+        (Vec2::new(x1, y0), Vec2::new(x1, y1)),
+        (Vec2::new(x1, y1), Vec2::new(x0, y1)),
+        (Vec2::new(x0, y1), Vec2::new(x0, y0)),
+    ]
+}
+
+fn spawn_lines(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    lines: &[(Vec2, Vec2)],
+    material: Handle<ColorMaterial>,
+) {
+    if lines.is_empty() {
+        return;
+    }
+    commands
+        .spawn(IslandBundle {
+            mesh: meshes.add(lines_to_mesh(lines)),
+            transform: Transform::from_translation(Vec3::new(0., 0., DEBUG_Z)),
+            material,
+            ..Default::default()
+        })
+        .with(CollisionDebugFlag);
+}
+
+//The debug entities are rebuilt every frame.
+//The island wireframes never change, so their meshes are only built once.
+fn collision_debug_system(
+    commands: &mut Commands,
+    debug: Res<CollisionDebug>,
+    mut island_wireframes: Local<HashMap<u32, Vec<(Handle<Mesh>, bool)>>>,
+    materials: Local<DebugMaterials>,
+    debug_query: Query<Entity, With<CollisionDebugFlag>>,
+    islands: Res<Islands>,
+    handles: Res<SeaHandles>,
+    pos_update: Res<PlayerPositionUpdate>,
+    area: Res<StreamingArea>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    for entity in debug_query.iter() {
+        commands.despawn_recursive(entity);
+    }
+    if !debug.enabled {
+        return;
+    }
+    //islands
+    for (island_id, island) in islands.0.iter().enumerate() {
+        if island.entity.is_none() {
+            continue;
+        }
+        let wireframes = island_wireframes
+            .entry(island_id as u32)
+            .or_insert_with(|| {
+                let mut wireframes = Vec::new();
+                if let Some(rigid_mesh) = &island.rigid_trimesh {
+                    let lines = trimesh_to_lines(rigid_mesh, 1., Vec2::zero());
+                    wireframes.push((meshes.add(lines_to_mesh(&lines)), true));
+                }
+                if let Some(friction_mesh) = &island.friction_trimesh {
+                    let lines = trimesh_to_lines(friction_mesh, 1., Vec2::zero());
+                    wireframes.push((meshes.add(lines_to_mesh(&lines)), false));
+                }
+                wireframes
+            });
+        for (mesh, rigid) in wireframes.iter() {
+            commands
+                .spawn(IslandBundle {
+                    mesh: mesh.clone(),
+                    transform: Transform {
+                        translation: Vec3::new(
+                            island.min_x as f32 * TILE_SIZE as f32 * ISLAND_SCALING,
+                            island.min_y as f32 * TILE_SIZE as f32 * ISLAND_SCALING,
+                            DEBUG_Z,
+                        ),
+                        scale: ISLAND_SCALING * Vec3::one(),
+                        ..Default::default()
+                    },
+                    material: if *rigid {
+                        materials.rigid.clone()
+                    } else {
+                        materials.friction.clone()
+                    },
+                    ..Default::default()
+                })
+                .with(CollisionDebugFlag);
+        }
+    }
+    //the boat hull
+    let player_translation = pos_update.translation.truncate();
+    if let Some(hull) = handles
+        .ships
+        .get(pos_update.ship_class)
+        .map(|ship| ship.meshes.get(pos_update.sprite_id as usize))
+        .flatten()
+    {
+        let lines = trimesh_to_lines(hull, ISLAND_SCALING, player_translation);
+        spawn_lines(commands, &mut meshes, &lines, materials.hull.clone());
+    }
+    //the contact point and its normal
+    if let (Some(island_id), Some((x, y, normal))) = (pos_update.island_id, pos_update.contact) {
+        let island = &islands.0[island_id as usize];
+        let point = Vec2::new(
+            x + (island.min_x * TILE_SIZE) as f32,
+            y + (island.min_y * TILE_SIZE) as f32,
+        ) * ISLAND_SCALING;
+        let normal = Vec2::new(normal.x, normal.y) * NORMAL_LENGTH;
+        let cross = Vec2::new(NORMAL_LENGTH, NORMAL_LENGTH) / 4.;
+        let lines = vec![
+            (point, point + normal),
+            (point - cross, point + cross),
+            (point + cross.perp(), point - cross.perp()),
+        ];
+        spawn_lines(commands, &mut meshes, &lines, materials.contact.clone());
+    }
+    //the query rectangles of the collision system
+    let (x, y) = area.center;
+    let (spawn_x, spawn_y) = area.spawn_distance;
+    let lines = tile_rect_to_lines((x - spawn_x, y - spawn_y), (x + spawn_x, y + spawn_y));
+    spawn_lines(commands, &mut meshes, &lines, materials.spawn_rect.clone());
+    let (despawn_x, despawn_y) = area.despawn_distance;
+    let lines = tile_rect_to_lines(
+        (x - despawn_x, y - despawn_y),
+        (x + despawn_x, y + despawn_y),
+    );
+    spawn_lines(
+        commands,
+        &mut meshes,
+        &lines,
+        materials.despawn_rect.clone(),
+    );
+    let lines = tile_rect_to_lines(
+        (x - CONTACT_DISTANCE, y - CONTACT_DISTANCE),
+        (x + CONTACT_DISTANCE, y + CONTACT_DISTANCE),
+    );
+    spawn_lines(
+        commands,
+        &mut meshes,
+        &lines,
+        materials.contact_rect.clone(),
+    );
+}
+
+fn unload_debug_system(
+    commands: &mut Commands,
+    debug_query: Query<Entity, With<CollisionDebugFlag>>,
+) {
+    for entity in debug_query.iter() {
+        commands.despawn_recursive(entity);
+    }
+}
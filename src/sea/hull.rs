@@ -0,0 +1,202 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+
+use crate::loading::GameState;
+
+use super::{
+    ai::AiShip,
+    player::{Player, PlayerPositionUpdate},
+};
+
+pub struct SeaHullPlugin;
+impl Plugin for SeaHullPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<ShipImpactEvent>()
+            .add_event::<ShipDamageEvent>()
+            .add_event::<ShipSunkEvent>()
+            .init_resource::<Shipwreck>()
+            .on_state_update(
+                GameState::STAGE,
+                GameState::Sea,
+                impact_damage_system.system(),
+            )
+            .on_state_update(GameState::STAGE, GameState::Sea, damage_system.system())
+            .on_state_update(GameState::STAGE, GameState::Sea, shipwreck_system.system())
+            .on_state_update(
+                GameState::STAGE,
+                GameState::Sea,
+                shipwreck_ui_system.system(),
+            )
+            .on_state_exit(
+                GameState::STAGE,
+                GameState::Sea,
+                remember_port_system.system(),
+            );
+    }
+}
+
+//below this speed, hitting the rocks doesn't damage the hull
+const SAFE_IMPACT_SPEED: f32 = 60.;
+const IMPACT_DAMAGE: f32 = 0.1;
+//a wrecked hull only keeps this share of the ship's speed
+const WRECKED_SPEED: f32 = 0.4;
+
+//The health of a ship. The max health comes from its ship class.
+#[derive(Clone, Debug)]
+pub struct Hull {
+    pub health: f32,
+    pub max_health: f32,
+}
+impl Hull {
+    pub fn new(max_health: f32) -> Self {
+        Hull {
+            health: max_health,
+            max_health,
+        }
+    }
+
+    //The share of health left, a hull without max health being whole
+    pub fn share(&self) -> f32 {
+        if self.max_health <= 0. {
+            return 1.;
+        }
+        (self.health / self.max_health).max(0.).min(1.)
+    }
+
+    //Changes the max health, keeping the share of health left
+    pub fn set_max_health(&mut self, max_health: f32) {
+        self.health = self.share() * max_health;
+        self.max_health = max_health;
+    }
+
+    //The multiplier applied to the acceleration of a damaged ship
+    pub fn speed_factor(&self) -> f32 {
+        WRECKED_SPEED + (1. - WRECKED_SPEED) * self.share()
+    }
+}
+
+//Sent when a ship hits a rigid coast, with its speed at that moment
+pub struct ShipImpactEvent {
+    pub entity: Entity,
+    pub speed: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum DamageSource {
+    Collision,
+    Cannon(Entity),
+}
+
+//Any system can damage a ship by sending this event
+pub struct ShipDamageEvent {
+    pub entity: Entity,
+    pub amount: f32,
+    pub source: DamageSource,
+}
+
+pub struct ShipSunkEvent {
+    pub entity: Entity,
+    pub source: DamageSource,
+}
+
+//Set when the player's ship sank, until it respawns
+pub struct Shipwreck {
+    pub sunk: bool,
+    //the player respawns where they last landed
+    pub respawn_point: Vec3,
+}
+impl Default for Shipwreck {
+    fn default() -> Self {
+        Shipwreck {
+            sunk: false,
+            respawn_point: Vec3::new(0., 0., 100.),
+        }
+    }
+}
+
+fn impact_damage_system(
+    mut impact_reader: EventReader<ShipImpactEvent>,
+    mut damage_events: ResMut<Events<ShipDamageEvent>>,
+) {
+    for impact in impact_reader.iter() {
+        let amount = (impact.speed.abs() - SAFE_IMPACT_SPEED) * IMPACT_DAMAGE;
+        if amount > 0. {
+            damage_events.send(ShipDamageEvent {
+                entity: impact.entity,
+                amount,
+                source: DamageSource::Collision,
+            })
+        }
+    }
+}
+
+fn damage_system(
+    mut damage_reader: EventReader<ShipDamageEvent>,
+    mut sunk_events: ResMut<Events<ShipSunkEvent>>,
+    mut hull_query: Query<&mut Hull>,
+) {
+    for damage in damage_reader.iter() {
+        if let Ok(mut hull) = hull_query.get_mut(damage.entity) {
+            if hull.health <= 0. {
+                continue;
+            }
+            hull.health -= damage.amount;
+            if hull.health <= 0. {
+                hull.health = 0.;
+                sunk_events.send(ShipSunkEvent {
+                    entity: damage.entity,
+                    source: damage.source,
+                })
+            }
+        }
+    }
+}
+
+fn shipwreck_system(
+    mut sunk_reader: EventReader<ShipSunkEvent>,
+    mut shipwreck: ResMut<Shipwreck>,
+    mut player_query: Query<(&mut Player, &mut Visible), Without<AiShip>>,
+) {
+    for sunk in sunk_reader.iter() {
+        if let Ok((mut player, mut visible)) = player_query.get_mut(sunk.entity) {
+            shipwreck.sunk = true;
+            player.stop();
+            visible.is_visible = false;
+        }
+    }
+}
+
+fn shipwreck_ui_system(
+    mut egui_context: ResMut<EguiContext>,
+    mut shipwreck: ResMut<Shipwreck>,
+    mut player_query: Query<
+        (&mut Player, &mut Hull, &mut Transform, &mut Visible),
+        Without<AiShip>,
+    >,
+) {
+    if !shipwreck.sunk {
+        return;
+    }
+    let ctx = &mut egui_context.ctx;
+    let mut respawn = false;
+    egui::Window::new("Shipwreck")
+        .collapsible(false)
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.label("Your ship sank.");
+            respawn = ui.button("Respawn at the last island").clicked();
+        });
+    if respawn {
+        shipwreck.sunk = false;
+        for (mut player, mut hull, mut transform, mut visible) in player_query.iter_mut() {
+            player.stop();
+            hull.health = hull.max_health;
+            transform.translation = shipwreck.respawn_point;
+            visible.is_visible = true;
+        }
+    }
+}
+
+fn remember_port_system(mut shipwreck: ResMut<Shipwreck>, pos_update: Res<PlayerPositionUpdate>) {
+    shipwreck.respawn_point = pos_update.translation;
+}
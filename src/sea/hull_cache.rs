@@ -0,0 +1,157 @@
+use std::{
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use image::GrayImage;
+use parry2d::{math::Point, shape::TriMesh};
+use seahash::SeaHasher;
+use serde::{Deserialize, Serialize};
+
+use crate::util::{hull_mesh_settings, image_to_trimeshes};
+
+use super::{
+    loader::{read_ships_config, ship_atlas},
+    player::ShipClass,
+    ISLAND_SCALING,
+};
+
+//Generating the hull meshes from the collision sheet takes a while, so they are generated once and written here.
+pub const HULL_CACHE_DIR: &str = "cache/";
+
+#[derive(Serialize, Deserialize)]
+struct CachedTriMesh {
+    vertices: Vec<(f32, f32)>,
+    indices: Vec<[u32; 3]>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct HullMeshCache {
+    key: u64,
+    meshes: Vec<CachedTriMesh>,
+}
+
+//The cache file of a ship class, ie "cache/ship_collisions_sheet2.sloop.hull.ron".
+//The classes sharing a sheet may cut it differently, so each one has its own file.
+pub fn hull_cache_path(ship_class: &ShipClass) -> PathBuf {
+    let sheet = Path::new(&ship_class.collision_sheet)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let class: String = ship_class
+        .name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    Path::new(HULL_CACHE_DIR).join(format!("{}.{}.hull.ron", sheet, class))
+}
+
+//The key changes whenever the image, the atlas layout, the scale or the generation settings change.
+fn hull_cache_key(image: &GrayImage, atlas: &TextureAtlas, scale: f32) -> u64 {
+    let mut hasher = SeaHasher::new();
+    image.dimensions().hash(&mut hasher);
+    image.as_raw().hash(&mut hasher);
+    for rect in &atlas.textures {
+        rect.min.x.to_bits().hash(&mut hasher);
+        rect.min.y.to_bits().hash(&mut hasher);
+        rect.max.x.to_bits().hash(&mut hasher);
+        rect.max.y.to_bits().hash(&mut hasher);
+    }
+    scale.to_bits().hash(&mut hasher);
+    format!("{:?}", hull_mesh_settings()).hash(&mut hasher);
+    hasher.finish()
+}
+
+fn load_cache(path: &Path, key: u64) -> Option<Vec<TriMesh>> {
+    let cache_string = std::fs::read_to_string(path).ok()?;
+    let cache: HullMeshCache = ron::from_str(&cache_string).ok()?;
+    if cache.key != key {
+        return None;
+    }
+    Some(
+        cache
+            .meshes
+            .into_iter()
+            .map(|mesh| {
+                TriMesh::new(
+                    mesh.vertices
+                        .into_iter()
+                        .map(|(x, y)| Point::new(x, y))
+                        .collect(),
+                    mesh.indices,
+                )
+            })
+            .collect(),
+    )
+}
+
+fn save_cache(path: &Path, key: u64, meshes: &[TriMesh]) -> std::io::Result<()> {
+    let cache = HullMeshCache {
+        key,
+        meshes: meshes
+            .iter()
+            .map(|mesh| CachedTriMesh {
+                vertices: mesh.vertices().iter().map(|p| (p.x, p.y)).collect(),
+                indices: mesh.indices().to_vec(),
+            })
+            .collect(),
+    };
+    let cache_string =
+        ron::to_string(&cache).map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, cache_string)
+}
+
+//Returns the hull meshes of the sheet, from the cache file when it is up to date.
+//Otherwise they are generated and the cache file is rewritten.
+pub fn get_hull_meshes(
+    image: &GrayImage,
+    atlas: &TextureAtlas,
+    scale: f32,
+    cache_path: &Path,
+) -> Vec<TriMesh> {
+    let key = hull_cache_key(image, atlas, scale);
+    if let Some(meshes) = load_cache(cache_path, key) {
+        return meshes;
+    }
+    let meshes = image_to_trimeshes(image, atlas, scale);
+    if let Err(e) = save_cache(cache_path, key, &meshes) {
+        warn!(
+            "could not write the hull cache {}: {}",
+            cache_path.display(),
+            e
+        );
+    }
+    meshes
+}
+
+//Entry point of `--build-hull-cache`, used to ship an up to date cache with releases.
+pub fn build_hull_cache() {
+    for ship_class in read_ships_config() {
+        let image = image::open(Path::new("assets").join(&ship_class.collision_sheet))
+            .expect("ship collision sheet not found")
+            .into_luma8();
+        let cache_path = hull_cache_path(&ship_class);
+        let meshes = get_hull_meshes(
+            &image,
+            &ship_atlas(Handle::default(), &ship_class),
+            1. / ISLAND_SCALING,
+            &cache_path,
+        );
+        println!(
+            "{} : {} hull meshes cached in {}",
+            ship_class.name,
+            meshes.len(),
+            cache_path.display()
+        );
+    }
+}
@@ -4,23 +4,38 @@ use bevy::render::pipeline::PipelineDescriptor;
 use bevy::{asset::LoadState, prelude::*};
 use parry2d::shape::TriMesh;
 
-use crate::{loading::GameState, util::texture_atlas_to_trimeshes};
+use crate::{loading::GameState, util::texture_to_image};
 
-use super::{player::PlayerPositionUpdate, worldgen::Biome, ISLAND_SCALING, TILE_SIZE};
+use super::{
+    hull::Shipwreck,
+    hull_cache::{get_hull_meshes, hull_cache_path},
+    player::{PlayerPositionUpdate, ShipClass},
+    worldgen::Biome,
+    ISLAND_SCALING, TILE_SIZE,
+};
 
 #[derive(Default)]
 pub struct SeaHandles {
     pub sea_pipeline: Handle<PipelineDescriptor>,
-    pub sea_sheet: Handle<TextureAtlas>,
     pub islands_sheet: Handle<TextureAtlas>,
-    pub boat: Handle<TextureAtlas>,
-    pub boat_collisions: Handle<TextureAtlas>,
-    pub boat_meshes: Vec<TriMesh>,
+    //in the same order as the ship classes
+    pub ships: Vec<ShipHandles>,
     pub islands_material: Handle<ColorMaterial>,
+    pub cannonball: Handle<ColorMaterial>,
+}
+
+#[derive(Default)]
+pub struct ShipHandles {
+    pub sprite_sheet: Handle<TextureAtlas>,
+    pub collisions: Handle<TextureAtlas>,
+    //one hull per frame, empty until the collision sheet is loaded
+    pub meshes: Vec<TriMesh>,
 }
 
 #[derive(Clone, Default)]
 pub struct BiomeConfig(pub Arc<Vec<(Handle<TextureAtlas>, Biome)>>);
+#[derive(Clone, Default)]
+pub struct ShipsConfig(pub Arc<Vec<ShipClass>>);
 pub struct SeaLoaderPlugin;
 impl Plugin for SeaLoaderPlugin {
     fn build(&self, app: &mut AppBuilder) {
@@ -50,7 +65,8 @@ impl Plugin for SeaLoaderPlugin {
             .add_system(on_loaded.system())
             .add_startup_system(setup.system())
             .init_resource::<SeaHandles>()
-            .insert_resource(worldgen_config);
+            .insert_resource(worldgen_config)
+            .insert_resource(ShipsConfig(Arc::new(read_ships_config())));
     }
 }
 
@@ -60,19 +76,24 @@ fn read_worldgen_config() -> Vec<Biome> {
     ron::from_str(&worldgen_config_string).expect("syntax error on worldgen config file")
 }
 
+pub(crate) fn read_ships_config() -> Vec<ShipClass> {
+    let ships_config_string =
+        std::fs::read_to_string("config/ships.ron").expect("ships config file not found");
+    let ships: Vec<ShipClass> =
+        ron::from_str(&ships_config_string).expect("syntax error on ships config file");
+    //the player and the AI ships need at least one class
+    assert!(!ships.is_empty(), "no ship class in ships config file");
+    ships
+}
+
 fn setup(
     asset_server: Res<AssetServer>,
+    ships_config: Res<ShipsConfig>,
     mut atlases: ResMut<Assets<TextureAtlas>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
     mut handles: ResMut<SeaHandles>,
 ) {
     //loading textures
-    let texture_handle_sea_spritesheet = asset_server.load("sprites/sea/seaTileSheet.png");
-
-    let sea_atlas =
-        TextureAtlas::from_grid(texture_handle_sea_spritesheet, Vec2::new(64., 64.), 3, 1);
-    handles.sea_sheet = atlases.add(sea_atlas);
-
     let texture_handle_islands_spritesheet = asset_server.load("sprites/sea/sheet2.png");
     let islands_atlas = TextureAtlas::from_grid_with_padding(
         texture_handle_islands_spritesheet,
@@ -83,30 +104,39 @@ fn setup(
     );
     handles.islands_material = materials.add(ColorMaterial::texture(islands_atlas.texture.clone()));
     handles.islands_sheet = atlases.add(islands_atlas);
-    let texture_handle = asset_server.load("sprites/sea/ship_sheet.png");
-    let texture_atlas = TextureAtlas::from_grid_with_padding(
-        texture_handle,
-        Vec2::new(133., 133.),
-        8,
-        1,
-        Vec2::new(1., 1.),
-    );
-    let texture_atlas_handle = atlases.add(texture_atlas);
-    handles.boat = texture_atlas_handle;
-    let texture_handle = asset_server.load("sprites/sea/ship_collisions_sheet2.png");
-    let texture_atlas = TextureAtlas::from_grid_with_padding(
+    let texture_handle_cannonball = asset_server.load("sprites/land/bullet.png");
+    handles.cannonball = materials.add(texture_handle_cannonball.into());
+    handles.ships = ships_config
+        .0
+        .iter()
+        .map(|ship_class| {
+            let texture_handle = asset_server.load(ship_class.sprite_sheet.as_str());
+            let sprite_sheet = atlases.add(ship_atlas(texture_handle, ship_class));
+            let texture_handle = asset_server.load(ship_class.collision_sheet.as_str());
+            let collisions = atlases.add(ship_atlas(texture_handle, ship_class));
+            ShipHandles {
+                sprite_sheet,
+                collisions,
+                meshes: Vec::new(),
+            }
+        })
+        .collect();
+}
+
+//The layout of the ship sheets : one row of frames, one for each orientation.
+pub(crate) fn ship_atlas(texture_handle: Handle<Texture>, ship_class: &ShipClass) -> TextureAtlas {
+    TextureAtlas::from_grid_with_padding(
         texture_handle,
-        Vec2::new(133., 133.),
-        8,
+        Vec2::new(ship_class.frame_size.0, ship_class.frame_size.1),
+        ship_class.frame_count,
         1,
         Vec2::new(1., 1.),
-    );
-    let texture_atlas_handle = atlases.add(texture_atlas);
-    handles.boat_collisions = texture_atlas_handle;
+    )
 }
 
 fn on_loaded(
     asset_server: Res<AssetServer>,
+    ships_config: Res<ShipsConfig>,
     mut handles: ResMut<SeaHandles>,
     atlases: Res<Assets<TextureAtlas>>,
     textures: Res<Assets<Texture>>,
@@ -115,23 +145,37 @@ fn on_loaded(
     if *loaded {
         return;
     }
-    let texture_atlas = atlases.get(handles.boat_collisions.clone()).unwrap();
-    if asset_server.get_load_state(texture_atlas.texture.clone()) != LoadState::Loaded {
-        return;
-    };
     *loaded = true;
-    let texture = textures.get(texture_atlas.texture.clone()).unwrap();
-    let trimeshes = texture_atlas_to_trimeshes(texture_atlas, texture, 1. / ISLAND_SCALING);
-    handles.boat_meshes = trimeshes;
+    for (ship_handles, ship_class) in handles.ships.iter_mut().zip(ships_config.0.iter()) {
+        if !ship_handles.meshes.is_empty() {
+            continue;
+        }
+        let texture_atlas = atlases.get(ship_handles.collisions.clone()).unwrap();
+        if asset_server.get_load_state(texture_atlas.texture.clone()) != LoadState::Loaded {
+            *loaded = false;
+            continue;
+        };
+        let texture = textures.get(texture_atlas.texture.clone()).unwrap();
+        let image = texture_to_image(texture).unwrap().into_luma8();
+        ship_handles.meshes = get_hull_meshes(
+            &image,
+            texture_atlas,
+            1. / ISLAND_SCALING,
+            &hull_cache_path(ship_class),
+        );
+    }
 }
 
 fn enter_island_system(
     keyboard_input: Res<Input<KeyCode>>,
     pos_update: Res<PlayerPositionUpdate>,
+    shipwreck: Res<Shipwreck>,
     mut state: ResMut<State<GameState>>,
 ) {
+    //a wreck can't land, the player respawns first
     if keyboard_input.just_pressed(KeyCode::Return)
         && pos_update.island_id.is_some()
+        && !shipwreck.sunk
         && state.current() == &GameState::Sea
     {
         state.overwrite_next(GameState::Land).unwrap();
@@ -1,10 +1,10 @@
-use crate::{background::BgFlag, loading::GameState};
+use crate::{background::BgFlag, clock::Tinted, lighting::LightSource, loading::GameState};
 
 use super::{
-    super::background::{BackgroundBundle, TileUv},
+    super::background::{BackgroundBundle, SeaWater, ShoreBundle},
     collision::IslandSpawnEvent,
     loader::SeaHandles,
-    worldgen::Island,
+    worldgen::{GenRessources, Island},
     ISLAND_SCALING, TILE_SIZE,
 };
 use bevy::{
@@ -14,9 +14,8 @@ use bevy::{
 };
 
 use serde::{Deserialize, Serialize};
-use std::time::Duration;
 
-#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum TileKind {
     Sand(bool),
     Forest,
@@ -48,27 +47,17 @@ impl Plugin for SeaMapPlugin {
     }
 }
 
+//The water plane follows the camera, the shader makes it look still
 fn move_anim_bg_system(
-    mut bg_query: Query<(&mut TileUv, &mut Transform)>,
-    camera_query: Query<(&Camera, &Transform)>,
-    time: Res<Time>,
-    mut timer: Local<Option<Timer>>,
+    mut bg_query: Query<&mut Transform, With<BgFlag>>,
+    camera_query: Query<&Transform, With<Camera>>,
 ) {
-    if timer.is_none() {
-        *timer = Some(Timer::new(Duration::from_millis(500), true));
-    }
-    let timer = timer.as_mut().unwrap();
-    for (mut bg, mut bg_transform) in bg_query.iter_mut() {
-        for (_, camera_transform) in camera_query.iter() {
+    for mut bg_transform in bg_query.iter_mut() {
+        for camera_transform in camera_query.iter() {
             bg_transform.translation.x = camera_transform.translation.x;
             bg_transform.translation.y = camera_transform.translation.y;
-        }
-        timer.tick(time.delta_seconds());
-        if timer.finished() {
-            bg.uv += Vec2::new(1. / 3., 0.);
-            if bg.uv.x >= 0.99 {
-                bg.uv = Vec2::new(0., 0.)
-            }
+            //the plane keeps covering the screen when zoomed out
+            bg_transform.scale = camera_transform.scale;
         }
     }
 }
@@ -76,7 +65,7 @@ fn move_anim_bg_system(
 fn load_map_system(
     commands: &mut Commands,
     mut meshes: ResMut<Assets<Mesh>>,
-    handles: Res<SeaHandles>,
+    gen_ressources: Local<GenRessources>,
 ) {
     //initializing the sea animation
     let mut transform = Transform::from_rotation(Quat::from_rotation_x(std::f32::consts::PI / 2.));
@@ -84,11 +73,7 @@ fn load_map_system(
     commands.spawn(BackgroundBundle {
         mesh: meshes.add(Mesh::from(shape::Plane { size: 10000.0 })),
         transform,
-        texture_atlas: handles.sea_sheet.clone(),
-        tile_uv: TileUv {
-            uv: Vec2::new(0.0, 0.0),
-            scale: 2.,
-        },
+        water: SeaWater::new(&gen_ressources.biome.water, 2.),
         ..Default::default()
     });
 }
@@ -192,6 +177,7 @@ fn spawn_island_system(
     mut islands: ResMut<Islands>,
     mut spawned_islands: Local<SpawnedIslands>,
     handles: Res<SeaHandles>,
+    gen_ressources: Local<GenRessources>,
 ) {
     for event in event_reader.iter() {
         let IslandSpawnEvent(island_id) = event;
@@ -221,6 +207,22 @@ fn spawn_island_system(
                 material: handles.islands_material.clone(),
                 ..Default::default()
             })
+            .with(Tinted(Color::WHITE))
+            .with_children(|parent| {
+                parent.spawn(ShoreBundle {
+                    mesh: island.shore_mesh.clone(),
+                    water: SeaWater::new(&gen_ressources.biome.water, 2.),
+                    ..Default::default()
+                });
+                for (kind, position) in island.lights.iter() {
+                    parent
+                        .spawn((
+                            Transform::from_translation(position.extend(0.)),
+                            GlobalTransform::default(),
+                        ))
+                        .with(LightSource(*kind));
+                }
+            })
             .current_entity();
         island.entity = entity;
     }
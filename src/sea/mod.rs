@@ -1,15 +1,42 @@
 use bevy::prelude::*;
 
+pub(crate) mod ai;
+pub(crate) mod camera;
+pub(crate) mod chart;
+pub(crate) mod clouds;
 //pub(crate) mod collision;
 pub mod collision;
+pub(crate) mod combat;
+pub(crate) mod currents;
+pub(crate) mod debug;
+pub(crate) mod hull;
+pub(crate) mod hull_cache;
 pub mod loader;
 pub(crate) mod map;
+pub(crate) mod navigation;
 pub mod player;
+pub(crate) mod ui;
+pub(crate) mod wake;
+pub(crate) mod weather;
+pub(crate) mod wind;
 pub(crate) mod worldgen;
+use ai::SeaAiPlugin;
+use camera::SeaCameraPlugin;
+use chart::SeaChartPlugin;
+use clouds::SeaCloudsPlugin;
 //use collision::SeaCollisionPlugin;
+use combat::SeaCombatPlugin;
+use currents::SeaCurrentsPlugin;
+use debug::SeaDebugPlugin;
+use hull::SeaHullPlugin;
 use loader::SeaLoaderPlugin;
 use map::SeaMapPlugin;
+use navigation::SeaNavigationPlugin;
 use player::SeaPlayerPlugin;
+use ui::SeaUiPlugin;
+use wake::SeaWakePlugin;
+use weather::SeaWeatherPlugin;
+use wind::SeaWindPlugin;
 use worldgen::SeaWorldGenPlugin;
 
 use self::collision::SeaCollisionPlugin;
@@ -24,6 +51,19 @@ impl Plugin for SeaPlugin {
             .add_plugin(SeaPlayerPlugin)
             .add_plugin(SeaMapPlugin)
             .add_plugin(SeaCollisionPlugin)
-            .add_plugin(SeaWorldGenPlugin);
+            .add_plugin(SeaWorldGenPlugin)
+            .add_plugin(SeaDebugPlugin)
+            .add_plugin(SeaWindPlugin)
+            .add_plugin(SeaWeatherPlugin)
+            .add_plugin(SeaCloudsPlugin)
+            .add_plugin(SeaCurrentsPlugin)
+            .add_plugin(SeaHullPlugin)
+            .add_plugin(SeaWakePlugin)
+            .add_plugin(SeaCombatPlugin)
+            .add_plugin(SeaAiPlugin)
+            .add_plugin(SeaNavigationPlugin)
+            .add_plugin(SeaCameraPlugin)
+            .add_plugin(SeaChartPlugin)
+            .add_plugin(SeaUiPlugin);
     }
 }
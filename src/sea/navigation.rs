@@ -0,0 +1,428 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    f32::consts::PI,
+};
+
+use bevy::{prelude::*, render::camera::Camera};
+
+use crate::loading::GameState;
+
+use super::{
+    ai::AiShip,
+    collision::SeaCollisionTree,
+    hull::Hull,
+    map::{Islands, TileKind},
+    player::{CollisionType, Player, PlayerPositionUpdate},
+    wind::{best_trim, sail_efficiency, Wind},
+    ISLAND_SCALING, TILE_SIZE,
+};
+
+pub struct SeaNavigationPlugin;
+impl Plugin for SeaNavigationPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.on_state_update(
+            GameState::STAGE,
+            GameState::Sea,
+            autopilot_input_system.system(),
+        )
+        .on_state_update(GameState::STAGE, GameState::Sea, autopilot_system.system());
+    }
+}
+
+//the side of a navigation cell, in tiles
+const CELL_SIZE: i32 = 2;
+//a cell is blocked when there is land this close to it, in tiles, so the hull fits
+const CLEARANCE: i32 = 1;
+//the cells this close to a blocked cell cost more, so the routes keep to open water
+const COAST_DISTANCE: i32 = 2;
+const COAST_COST: u32 = 20;
+const STRAIGHT_COST: u32 = 10;
+const DIAGONAL_COST: u32 = 14;
+//the search gives up after this many cells, and goes as close as it got
+const MAX_EXPANDED: usize = 5000;
+//distances in pixels
+const WAYPOINT_DISTANCE: f32 = 48.;
+pub const ARRIVAL_DISTANCE: f32 = 100.;
+//how far ahead, in tiles, the ships look for land when steering
+const LOOKAHEAD: i32 = 4;
+//the ships don't sail courses getting less than this share of the wind
+const MIN_SAIL_EFFICIENCY: f32 = 0.3;
+const AUTOPILOT_SAIL: f32 = 1.;
+
+fn to_tile(position: Vec2) -> Vec2 {
+    (position / (TILE_SIZE as f32 * ISLAND_SCALING)).floor()
+}
+
+fn to_cell(position: Vec2) -> (i32, i32) {
+    let tile = to_tile(position);
+    (
+        (tile.x as i32).div_euclid(CELL_SIZE),
+        (tile.y as i32).div_euclid(CELL_SIZE),
+    )
+}
+
+fn cell_center(cell: (i32, i32)) -> Vec2 {
+    (Vec2::new(cell.0 as f32, cell.1 as f32) * CELL_SIZE as f32
+        + Vec2::one() * CELL_SIZE as f32 / 2.)
+        * TILE_SIZE as f32
+        * ISLAND_SCALING
+}
+
+//A coarse grid over the generated islands, computed lazily during a search
+struct NavigationGrid<'a> {
+    islands: &'a Islands,
+    kdtree: &'a SeaCollisionTree,
+    blocked: HashMap<(i32, i32), bool>,
+}
+impl<'a> NavigationGrid<'a> {
+    fn new(islands: &'a Islands, kdtree: &'a SeaCollisionTree) -> Self {
+        NavigationGrid {
+            islands,
+            kdtree,
+            blocked: HashMap::new(),
+        }
+    }
+
+    fn is_blocked(&mut self, cell: (i32, i32)) -> bool {
+        let (islands, kdtree) = (self.islands, self.kdtree);
+        *self.blocked.entry(cell).or_insert_with(|| {
+            let (min_x, min_y) = (
+                cell.0 * CELL_SIZE - CLEARANCE,
+                cell.1 * CELL_SIZE - CLEARANCE,
+            );
+            let (max_x, max_y) = (
+                (cell.0 + 1) * CELL_SIZE - 1 + CLEARANCE,
+                (cell.1 + 1) * CELL_SIZE - 1 + CLEARANCE,
+            );
+            if kdtree
+                .0
+                .query_rect(min_x, max_x, min_y, max_y)
+                .next()
+                .is_none()
+            {
+                return false;
+            }
+            (min_x..=max_x).any(|x| {
+                (min_y..=max_y).any(|y| kdtree.tile_at(islands, x, y) != TileKind::Sea(false))
+            })
+        })
+    }
+
+    //the cost of entering a cell, besides the distance
+    fn coast_cost(&mut self, cell: (i32, i32)) -> u32 {
+        let near_coast = (-COAST_DISTANCE..=COAST_DISTANCE).any(|i| {
+            (-COAST_DISTANCE..=COAST_DISTANCE).any(|j| self.is_blocked((cell.0 + i, cell.1 + j)))
+        });
+        if near_coast {
+            COAST_COST
+        } else {
+            0
+        }
+    }
+
+    //whether a straight line between two cells only crosses free cells
+    fn line_is_clear(&mut self, from: (i32, i32), to: (i32, i32)) -> bool {
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        let steps = dx.abs().max(dy.abs()) * 2;
+        (0..=steps).all(|step| {
+            let t = if steps == 0 {
+                0.
+            } else {
+                step as f32 / steps as f32
+            };
+            let cell = (
+                (from.0 as f32 + 0.5 + dx as f32 * t).floor() as i32,
+                (from.1 as f32 + 0.5 + dy as f32 * t).floor() as i32,
+            );
+            !self.is_blocked(cell)
+        })
+    }
+}
+
+fn heuristic(from: (i32, i32), to: (i32, i32)) -> u32 {
+    let (dx, dy) = ((to.0 - from.0).abs() as u32, (to.1 - from.1).abs() as u32);
+    STRAIGHT_COST * dx.max(dy) + (DIAGONAL_COST - STRAIGHT_COST) * dx.min(dy)
+}
+
+//A route from `from` to within `tolerance` pixels of `to`, avoiding the coasts of the generated islands.
+//It is a list of waypoints, in pixels. If `to` can't be reached, the route goes as close as possible.
+pub fn find_route(
+    islands: &Islands,
+    kdtree: &SeaCollisionTree,
+    from: Vec2,
+    to: Vec2,
+    tolerance: f32,
+) -> Vec<Vec2> {
+    let mut grid = NavigationGrid::new(islands, kdtree);
+    let (start, goal) = (to_cell(from), to_cell(to));
+    let mut open = BinaryHeap::new();
+    let mut came_from = HashMap::new();
+    let mut costs = HashMap::new();
+    open.push(Reverse((heuristic(start, goal), start)));
+    costs.insert(start, 0);
+    let mut closest = start;
+    let mut expanded = 0;
+    while let Some(Reverse((_, cell))) = open.pop() {
+        if cell_center(cell).distance(to) <= tolerance {
+            closest = cell;
+            break;
+        }
+        if heuristic(cell, goal) < heuristic(closest, goal) {
+            closest = cell;
+        }
+        if expanded >= MAX_EXPANDED {
+            break;
+        }
+        expanded += 1;
+        let cost = costs[&cell];
+        for (i, j) in [
+            (1, 0),
+            (-1, 0),
+            (0, 1),
+            (0, -1),
+            (1, 1),
+            (1, -1),
+            (-1, 1),
+            (-1, -1),
+        ]
+        .iter()
+        {
+            let next = (cell.0 + i, cell.1 + j);
+            if grid.is_blocked(next) {
+                continue;
+            }
+            let diagonal = *i != 0 && *j != 0;
+            //no cutting the corners of the coasts
+            if diagonal
+                && (grid.is_blocked((cell.0 + i, cell.1)) || grid.is_blocked((cell.0, cell.1 + j)))
+            {
+                continue;
+            }
+            let step = if diagonal {
+                DIAGONAL_COST
+            } else {
+                STRAIGHT_COST
+            };
+            let next_cost = cost + step + grid.coast_cost(next);
+            if costs
+                .get(&next)
+                .map_or(true, |previous| next_cost < *previous)
+            {
+                costs.insert(next, next_cost);
+                came_from.insert(next, cell);
+                open.push(Reverse((next_cost + heuristic(next, goal), next)));
+            }
+        }
+    }
+
+    let mut cells = vec![closest];
+    while let Some(previous) = came_from.get(cells.last().unwrap()) {
+        cells.push(*previous);
+    }
+    cells.reverse();
+
+    //only keeps the cells where the route has to turn
+    let mut route = Vec::new();
+    let mut anchor = 0;
+    while anchor < cells.len() - 1 {
+        let mut next = anchor + 1;
+        while next + 1 < cells.len() && grid.line_is_clear(cells[anchor], cells[next + 1]) {
+            next += 1;
+        }
+        route.push(cell_center(cells[next]));
+        anchor = next;
+    }
+    if closest == goal {
+        if let Some(last) = route.last_mut() {
+            *last = to;
+        }
+    }
+    route
+}
+
+//Whether a ship can sail `LOOKAHEAD` tiles toward `angle` without meeting land
+fn is_clear(islands: &Islands, kdtree: &SeaCollisionTree, position: Vec2, angle: f32) -> bool {
+    let direction = Vec2::new(angle.cos(), angle.sin());
+    (1..=LOOKAHEAD).all(|distance| {
+        let tile = to_tile(position) + direction * distance as f32;
+        kdtree.tile_at(islands, tile.x as i32, tile.y as i32) == TileKind::Sea(false)
+    })
+}
+
+//Steers a ship toward `target` with its helm, sails and trim, around the islands in the way and without sailing into the wind
+pub fn steer(
+    player: &mut Player,
+    position: Vec2,
+    target: Vec2,
+    wind: Vec2,
+    islands: &Islands,
+    kdtree: &SeaCollisionTree,
+    sail: f32,
+) {
+    let offset = target - position;
+    let desired = f32::atan2(offset.y, offset.x);
+    let can_sail = |angle: f32| {
+        sail_efficiency(angle, wind, best_trim(angle, wind)) >= MIN_SAIL_EFFICIENCY * wind.length()
+    };
+    //the directions closest to the target come first
+    let course = (0..8)
+        .map(|i| desired + ((i + 1) / 2) as f32 * PI / 4. * if i % 2 == 0 { 1. } else { -1. })
+        .find(|angle| can_sail(*angle) && is_clear(islands, kdtree, position, *angle))
+        .unwrap_or(desired);
+    let difference = (course - player.rotation() + PI).rem_euclid(2. * PI) - PI;
+    player.set_helm(difference / (PI / 4.));
+    player.sail = sail;
+    player.trim = best_trim(player.heading(), wind);
+}
+
+//Steers a ship along a route, dropping the waypoints it reaches.
+//Returns true once the route is done.
+pub fn follow_route(
+    player: &mut Player,
+    position: Vec2,
+    route: &mut Vec<Vec2>,
+    wind: Vec2,
+    islands: &Islands,
+    kdtree: &SeaCollisionTree,
+    sail: f32,
+) -> bool {
+    while route.first().map_or(false, |waypoint| {
+        waypoint.distance(position) < WAYPOINT_DISTANCE
+    }) {
+        route.remove(0);
+    }
+    match route.first() {
+        Some(waypoint) => {
+            steer(player, position, *waypoint, wind, islands, kdtree, sail);
+            false
+        }
+        None => true,
+    }
+}
+
+//Sails the player's ship along a route
+pub struct Autopilot {
+    pub destination: Vec2,
+    route: Vec<Vec2>,
+}
+
+//Right click sails to the clicked point, N to the closest island
+fn autopilot_input_system(
+    commands: &mut Commands,
+    mouse_input: Res<Input<MouseButton>>,
+    keyboard_input: Res<Input<KeyCode>>,
+    windows: Res<Windows>,
+    islands: Res<Islands>,
+    kdtree: Res<SeaCollisionTree>,
+    pos_update: Res<PlayerPositionUpdate>,
+    camera_query: Query<&Transform, With<Camera>>,
+    player_query: Query<(Entity, &Transform), (With<Player>, Without<AiShip>)>,
+) {
+    let position = pos_update.translation.truncate();
+    let destination = if mouse_input.just_pressed(MouseButton::Right) {
+        let window = match windows.get_primary() {
+            Some(window) => window,
+            None => return,
+        };
+        let cursor = match window.cursor_position() {
+            Some(cursor) => cursor,
+            None => return,
+        };
+        camera_query.iter().next().map(|camera_transform| {
+            let offset = cursor - Vec2::new(window.width(), window.height()) / 2.;
+            (
+                camera_transform.translation.truncate()
+                    + offset * camera_transform.scale.truncate(),
+                ARRIVAL_DISTANCE,
+            )
+        })
+    } else if keyboard_input.just_pressed(KeyCode::N) {
+        //the island the ship is at doesn't count
+        islands
+            .0
+            .iter()
+            .enumerate()
+            .filter(|(island_id, _)| pos_update.island_id != Some(*island_id as u32))
+            .map(|(_, island)| {
+                let min = Vec2::new(island.min_x as f32, island.min_y as f32);
+                let max = Vec2::new(island.max_x as f32 + 1., island.max_y as f32 + 1.);
+                let tile_size = TILE_SIZE as f32 * ISLAND_SCALING;
+                let center = (min + max) / 2. * tile_size;
+                let half_size = (max - min) / 2. * tile_size;
+                (center, half_size.max_element() + ARRIVAL_DISTANCE)
+            })
+            .min_by(|(a, _), (b, _)| {
+                a.distance(position)
+                    .partial_cmp(&b.distance(position))
+                    .unwrap()
+            })
+    } else {
+        None
+    };
+    if let Some((destination, tolerance)) = destination {
+        for (entity, transform) in player_query.iter() {
+            let route = find_route(
+                &islands,
+                &kdtree,
+                transform.translation.truncate(),
+                destination,
+                tolerance,
+            );
+            commands.insert_one(entity, Autopilot { destination, route });
+        }
+    }
+}
+
+fn autopilot_system(
+    commands: &mut Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    wind: Res<Wind>,
+    islands: Res<Islands>,
+    kdtree: Res<SeaCollisionTree>,
+    pos_update: Res<PlayerPositionUpdate>,
+    mut player_query: Query<(Entity, &mut Autopilot, &mut Player, &Transform, &Hull)>,
+) {
+    //taking the helm, the sails or the trim stops the autopilot
+    let manual = [
+        KeyCode::Left,
+        KeyCode::Right,
+        KeyCode::Up,
+        KeyCode::Down,
+        KeyCode::Q,
+        KeyCode::E,
+    ]
+    .iter()
+    .any(|key| keyboard_input.just_pressed(*key));
+    for (entity, mut autopilot, mut player, transform, hull) in player_query.iter_mut() {
+        if manual || hull.health <= 0. {
+            //the rowing away from the rocks stops with the autopilot, unless the player rows
+            if !keyboard_input.pressed(KeyCode::Down) {
+                player.set_rowing(false);
+            }
+            commands.remove_one::<Autopilot>(entity);
+            continue;
+        }
+        let position = transform.translation.truncate();
+        let arrived = follow_route(
+            &mut player,
+            position,
+            &mut autopilot.route,
+            wind.at(position),
+            &islands,
+            &kdtree,
+            AUTOPILOT_SAIL,
+        );
+        if arrived {
+            player.sail = 0.;
+            player.set_helm(0.);
+            commands.remove_one::<Autopilot>(entity);
+        } else if matches!(pos_update.collision_status, CollisionType::Rigid) {
+            //stuck against the rocks, the ship rows away
+            player.sail = 0.;
+            player.set_rowing(true);
+        } else {
+            player.set_rowing(false);
+        }
+    }
+}
@@ -1,11 +1,25 @@
-use bevy::{prelude::*, render::camera::Camera};
+use bevy::prelude::*;
 use parry2d::{math::Vector, na::Unit};
+use serde::{Deserialize, Serialize};
 
 use std::f32::consts::PI;
 
-use crate::loading::GameState;
+use crate::{
+    lighting::{LightKind, LightSource},
+    loading::GameState,
+};
 
-use super::{loader::SeaHandles, ISLAND_SCALING, TILE_SIZE};
+use super::{
+    ai::AiShip,
+    collision::SeaCollisionTree,
+    combat::Cannons,
+    currents::Currents,
+    hull::{Hull, ShipImpactEvent, Shipwreck},
+    loader::{SeaHandles, ShipsConfig},
+    weather::Weather,
+    wind::{sail_efficiency, Wind},
+    ISLAND_SCALING, TILE_SIZE,
+};
 pub struct SeaPlayerPlugin;
 impl Plugin for SeaPlayerPlugin {
     fn build(&self, app: &mut AppBuilder) {
@@ -19,10 +33,20 @@ impl Plugin for SeaPlayerPlugin {
                 GameState::Sea,
                 keyboard_input_system.system(),
             )
+            .on_state_update(
+                GameState::STAGE,
+                GameState::Sea,
+                ship_orientation_system.system(),
+            )
             .on_state_update(
                 GameState::STAGE,
                 GameState::Sea,
                 player_orientation.system(),
+            )
+            .on_state_update(
+                GameState::STAGE,
+                GameState::Sea,
+                switch_ship_system.system(),
             );
     }
 }
@@ -30,15 +54,37 @@ impl Plugin for SeaPlayerPlugin {
 struct PlayerSave {
     translation: Vec3,
     player: Player,
+    //None until the ship class is known
+    hull: Option<Hull>,
 }
 impl Default for PlayerSave {
     fn default() -> Self {
         Self {
             translation: Vec3::new(0., 0., 100.),
             player: Player::default(),
+            hull: None,
         }
     }
 }
+//A kind of ship, read from config/ships.ron
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ShipClass {
+    pub name: String,
+    pub sprite_sheet: String,
+    pub collision_sheet: String,
+    pub frame_count: usize,
+    pub frame_size: (f32, f32),
+    pub friction: f32,
+    pub rotation_friction: f32,
+    pub sail_power: f32,
+    pub oar_acceleration: f32,
+    pub turn_rate: f32,
+    pub cargo_capacity: u32,
+    pub hull: f32,
+    //cannons on each side
+    pub cannons: u32,
+}
+
 #[derive(Clone)]
 pub struct Player {
     rotation: f32,
@@ -48,6 +94,19 @@ pub struct Player {
     acceleration: f32,
     friction: f32,
     rotation_friction: f32,
+    //the stats below come from the ship class
+    sail_power: f32,
+    oar_acceleration: f32,
+    turn_rate: f32,
+    frame_count: usize,
+    pub class: usize,
+    //how much the sails are raised, from 0 (furled) to 1
+    pub sail: f32,
+    //the angle of the sails, from 0 (sheeted in) to 1 (let out)
+    pub trim: f32,
+    rowing: f32,
+    //while stuck against the rocks, whether the ship was going forward
+    stuck_forward: Option<bool>,
 }
 impl Default for Player {
     fn default() -> Player {
@@ -59,10 +118,143 @@ impl Default for Player {
             rotation_acceleration: 0.,
             friction: 0.2,
             rotation_friction: 10.,
+            sail_power: 100.,
+            oar_acceleration: 20.,
+            turn_rate: 20.,
+            frame_count: 8,
+            class: 0,
+            sail: 0.,
+            trim: 0.5,
+            rowing: 0.,
+            stuck_forward: None,
         }
     }
 }
+impl Player {
+    pub fn new(class: usize, ship_class: &ShipClass, rotation: f32) -> Self {
+        let mut player = Player {
+            rotation,
+            ..Default::default()
+        };
+        player.set_class(class, ship_class);
+        player
+    }
+
+    //Sets the ship class and its stats, keeping the current movement.
+    pub fn set_class(&mut self, class: usize, ship_class: &ShipClass) {
+        self.class = class;
+        self.friction = ship_class.friction;
+        self.rotation_friction = ship_class.rotation_friction;
+        self.sail_power = ship_class.sail_power;
+        self.oar_acceleration = ship_class.oar_acceleration;
+        self.turn_rate = ship_class.turn_rate;
+        self.frame_count = ship_class.frame_count;
+    }
+
+    //Stops the ship and furls the sails
+    pub fn stop(&mut self) {
+        self.speed = 0.;
+        self.acceleration = 0.;
+        self.rotation_speed = 0.;
+        self.rotation_acceleration = 0.;
+        self.sail = 0.;
+        self.rowing = 0.;
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    //The direction the ship moves to, rounded to the 8 directions of the sprites
+    pub fn heading(&self) -> f32 {
+        (0.5 + 8. * self.rotation / (2. * PI)).floor() / 8.0 * (2. * PI)
+    }
 
+    pub fn rotation(&self) -> f32 {
+        self.rotation
+    }
+
+    //Turns the rudder, from -1 (hard to starboard) to 1 (hard to port)
+    pub fn set_helm(&mut self, helm: f32) {
+        self.rotation_acceleration = helm.max(-1.).min(1.) * self.turn_rate;
+    }
+
+    //Rows backward, only when the sails are furled
+    pub fn set_rowing(&mut self, rowing: bool) {
+        self.rowing = if rowing && self.sail <= 0. {
+            -self.oar_acceleration
+        } else {
+            0.
+        };
+    }
+
+    //One step of the ship physics, shared by the player and the AI ships.
+    //`wind` and `current` are sampled at the ship position.
+    //Returns the speed of the ship when it hits a rigid coast.
+    pub fn sail(
+        &mut self,
+        transform: &mut Transform,
+        collision: &CollisionType,
+        speed_factor: f32,
+        wind: Vec2,
+        current: Vec2,
+        delta: f32,
+    ) -> Option<f32> {
+        let mut impact = None;
+        self.rotation_speed +=
+            (self.rotation_acceleration - self.rotation_speed * self.rotation_friction) * delta;
+
+        let rounded_angle = self.heading();
+        let (s, c) = f32::sin_cos(rounded_angle);
+        self.acceleration =
+            (self.sail_power * self.sail * sail_efficiency(rounded_angle, wind, self.trim)
+                + self.rowing)
+                * speed_factor;
+        match collision {
+            CollisionType::None => {
+                self.stuck_forward = None;
+                self.rotation = (self.rotation + self.rotation_speed * delta) % (2. * PI);
+                self.speed += (self.acceleration
+                    - (1. + self.rotation_speed.abs()) * self.speed * self.friction)
+                    * delta;
+                transform.translation.x += c * self.speed * delta;
+                transform.translation.y += s * self.speed * delta;
+            }
+            CollisionType::Friction => {
+                self.stuck_forward = None;
+                self.speed += (self.acceleration - self.speed * self.friction * 20.) * delta;
+                transform.translation.x += c * self.speed * delta;
+                transform.translation.y += s * self.speed * delta;
+            }
+            CollisionType::Rigid => {
+                self.speed += (self.acceleration - self.speed * self.friction * 20.) * delta;
+                if self.stuck_forward.is_none() {
+                    self.stuck_forward = Some(self.speed > 0.);
+                    impact = Some(self.speed);
+                }
+                if (self.stuck_forward.unwrap() && self.speed < 0.)
+                    || (!self.stuck_forward.unwrap() && self.speed > 0.)
+                {
+                    transform.translation.x += c * self.speed * delta;
+                    transform.translation.y += s * self.speed * delta;
+                } else {
+                    self.speed = 0.;
+                }
+            }
+        }
+        //the currents drift the ship, except when it is stuck against the rocks
+        if !matches!(collision, CollisionType::Rigid) {
+            transform.translation += (current * delta).extend(0.);
+        }
+        impact
+    }
+}
+
+const SAIL_STEP: f32 = 0.25;
+//trim change per second while the key is held
+const TRIM_SPEED: f32 = 0.5;
+
+#[derive(Clone, Copy)]
 pub enum CollisionType {
     None,
     Friction,
@@ -78,6 +270,7 @@ pub struct PlayerPositionUpdate {
     pub island_id: Option<u32>,
     pub contact: Option<(f32, f32, Unit<Vector<f32>>)>,
     pub sprite_id: u32,
+    pub ship_class: usize,
 }
 impl PlayerPositionUpdate {
     fn update(&mut self, t: &Vec3) {
@@ -93,6 +286,7 @@ impl Default for PlayerPositionUpdate {
             x: 0,
             y: 0,
             sprite_id: 0,
+            ship_class: 0,
             island_id: None,
             translation: Vec3::default(),
             changed_tile: true,
@@ -102,122 +296,174 @@ impl Default for PlayerPositionUpdate {
     }
 }
 
-fn load_system(commands: &mut Commands, handles: Res<SeaHandles>, save: Res<PlayerSave>) {
+fn load_system(
+    commands: &mut Commands,
+    handles: Res<SeaHandles>,
+    ships_config: Res<ShipsConfig>,
+    save: Res<PlayerSave>,
+) {
+    let mut player = save.player.clone();
+    //the config may have changed since the save
+    let class = player.class.min(ships_config.0.len() - 1);
+    player.set_class(class, &ships_config.0[class]);
+    let mut hull = save
+        .hull
+        .clone()
+        .unwrap_or_else(|| Hull::new(ships_config.0[class].hull));
+    hull.set_max_health(ships_config.0[class].hull);
     commands
         .spawn(SpriteSheetBundle {
-            texture_atlas: handles.boat.clone(),
+            texture_atlas: handles.ships[class].sprite_sheet.clone(),
             transform: Transform::from_translation(save.translation),
             ..Default::default()
         })
-        .with(save.player.clone());
+        .with(Tinted(Color::WHITE))
+        .with(LightSource(LightKind::Lantern))
+        .with(player)
+        .with(hull)
+        .with(Cannons::new(ships_config.0[class].cannons));
 }
 
 fn unload_system(
     commands: &mut Commands,
     mut save: ResMut<PlayerSave>,
-    player_query: Query<(Entity, &Transform, &Player)>,
+    player_query: Query<(Entity, &Transform, &Player, &Hull), Without<AiShip>>,
 ) {
-    for (entity, transform, player) in player_query.iter() {
+    for (entity, transform, player, hull) in player_query.iter() {
         save.translation = transform.translation;
         save.player = player.clone();
+        save.hull = Some(hull.clone());
         commands.despawn_recursive(entity);
     }
 }
 
 fn keyboard_input_system(
+    time: Res<Time>,
     keyboard_input: Res<Input<KeyCode>>,
-    mut player_query: Query<&mut Player>,
+    shipwreck: Res<Shipwreck>,
+    mut player_query: Query<&mut Player, Without<AiShip>>,
 ) {
+    if shipwreck.sunk {
+        return;
+    }
     for mut player in player_query.iter_mut() {
-        if keyboard_input.just_released(KeyCode::Up) || keyboard_input.just_released(KeyCode::Down)
-        {
-            player.acceleration = 0.;
-        }
-
         if keyboard_input.just_released(KeyCode::Right)
             || keyboard_input.just_released(KeyCode::Left)
         {
             player.rotation_acceleration = 0.;
         }
 
+        //rowing starts with a new press of down once the sails are furled, and lasts while it is held.
+        //Holding the press that furled the sails doesn't row.
+        //The rowing is only changed on the presses, so the autopilot can row too.
+        let furled = player.sail <= 0.;
         if keyboard_input.just_pressed(KeyCode::Up) {
-            player.acceleration = 100.;
+            player.sail = (player.sail + SAIL_STEP).min(1.);
+            player.set_rowing(false);
         } else if keyboard_input.just_pressed(KeyCode::Down) {
-            player.acceleration = -100.;
+            player.sail = (player.sail - SAIL_STEP).max(0.);
+            player.set_rowing(furled);
+        } else if keyboard_input.just_released(KeyCode::Down) {
+            player.set_rowing(false);
+        }
+
+        if keyboard_input.pressed(KeyCode::Q) {
+            player.trim = (player.trim - TRIM_SPEED * time.delta_seconds()).max(0.);
+        } else if keyboard_input.pressed(KeyCode::E) {
+            player.trim = (player.trim + TRIM_SPEED * time.delta_seconds()).min(1.);
         }
 
         if keyboard_input.just_pressed(KeyCode::Right) {
-            player.rotation_acceleration = -20.;
+            player.rotation_acceleration = -player.turn_rate;
         } else if keyboard_input.just_pressed(KeyCode::Left) {
-            player.rotation_acceleration = 20.;
+            player.rotation_acceleration = player.turn_rate;
         }
     }
 }
 
 fn player_movement(
     time: Res<Time>,
-    mut stuck_forward: Local<Option<bool>>,
+    wind: Res<Wind>,
+    weather: Res<Weather>,
+    currents: Res<Currents>,
+    kdtree: Res<SeaCollisionTree>,
     mut pos_update: ResMut<PlayerPositionUpdate>,
-    mut player_query: Query<(&mut Player, &mut Transform)>,
-    mut camera_query: Query<(&Camera, &mut Transform)>,
+    mut impact_events: ResMut<Events<ShipImpactEvent>>,
+    mut player_query: Query<(Entity, &mut Player, &mut Transform, &Hull), Without<AiShip>>,
 ) {
-    for (mut player, mut player_transform) in player_query.iter_mut() {
-        player.rotation_speed += (player.rotation_acceleration
-            - player.rotation_speed * player.rotation_friction)
-            * time.delta_seconds();
-
-        let rounded_angle = (0.5 + 8. * player.rotation / (2. * PI)).floor() / 8.0 * (2. * PI);
-        let (s, c) = f32::sin_cos(rounded_angle);
-        match pos_update.collision_status {
-            CollisionType::None => {
-                *stuck_forward = None;
-                player.rotation =
-                    (player.rotation + player.rotation_speed * time.delta_seconds()) % (2. * PI);
-                player.speed += (player.acceleration
-                    - (1. + player.rotation_speed.abs()) * player.speed * player.friction)
-                    * time.delta_seconds();
-                player_transform.translation.x += c * player.speed * time.delta_seconds();
-                player_transform.translation.y += s * player.speed * time.delta_seconds();
-            }
-            CollisionType::Friction => {
-                *stuck_forward = None;
-                player.speed += (player.acceleration - player.speed * player.friction * 20.)
-                    * time.delta_seconds();
-                player_transform.translation.x += c * player.speed * time.delta_seconds();
-                player_transform.translation.y += s * player.speed * time.delta_seconds();
-            }
-            CollisionType::Rigid => {
-                player.speed += (player.acceleration - player.speed * player.friction * 20.)
-                    * time.delta_seconds();
-                if stuck_forward.is_none() {
-                    *stuck_forward = Some(player.speed > 0.)
-                }
-                if (stuck_forward.unwrap() && player.speed < 0.)
-                    || (!stuck_forward.unwrap() && player.speed > 0.)
-                {
-                    player_transform.translation.x += c * player.speed * time.delta_seconds();
-                    player_transform.translation.y += s * player.speed * time.delta_seconds();
-                } else {
-                    player.speed = 0.;
-                }
-            }
+    for (entity, mut player, mut player_transform, hull) in player_query.iter_mut() {
+        //a sunk ship doesn't move anymore
+        if hull.health <= 0. {
+            continue;
         }
-        pos_update.update(&player_transform.translation);
-        for (_camera, mut camera_transform) in camera_query.iter_mut() {
-            camera_transform.translation.x = player_transform.translation.x;
-            camera_transform.translation.y = player_transform.translation.y;
+        let position = player_transform.translation.truncate();
+        if let Some(speed) = player.sail(
+            &mut player_transform,
+            &pos_update.collision_status,
+            hull.speed_factor(),
+            wind.at(position),
+            currents.at(position, &kdtree),
+            time.delta_seconds(),
+        ) {
+            impact_events.send(ShipImpactEvent { entity, speed });
         }
+        //the gusts don't push a ship against the coast
+        if let CollisionType::None = pos_update.collision_status {
+            player_transform.translation += (weather.gust() * time.delta_seconds()).extend(0.);
+        }
+        pos_update.update(&player_transform.translation);
+    }
+}
+
+//Sets the sprite matching the orientation of every ship
+fn ship_orientation_system(mut ship_query: Query<(&Player, &mut TextureAtlasSprite)>) {
+    for (player, mut sprite) in ship_query.iter_mut() {
+        //the first frame of the sheets is 5/8 of a turn from the x axis
+        let frames = player.frame_count as i32;
+        sprite.index = ((0.5 - frames as f32 * player.rotation / (2. * std::f32::consts::PI))
+            .floor() as i32
+            + 5 * frames / 8)
+            .rem_euclid(frames) as u32;
     }
 }
 
 fn player_orientation(
-    mut player_query: Query<(&Player, &mut TextureAtlasSprite)>,
+    player_query: Query<(&Player, &TextureAtlasSprite), Without<AiShip>>,
     mut player_pos_update: ResMut<PlayerPositionUpdate>,
 ) {
-    for (player, mut sprite) in player_query.iter_mut() {
-        sprite.index = (((0.5 - 8. * player.rotation / (2. * std::f32::consts::PI)).floor() as i32
-            + 21)
-            % 8) as u32;
+    for (player, sprite) in player_query.iter() {
         player_pos_update.sprite_id = sprite.index;
+        player_pos_update.ship_class = player.class;
+    }
+}
+
+//Tab changes the ship for the next ship class
+fn switch_ship_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    handles: Res<SeaHandles>,
+    ships_config: Res<ShipsConfig>,
+    mut player_query: Query<
+        (
+            &mut Player,
+            &mut Hull,
+            &mut Cannons,
+            &mut Handle<TextureAtlas>,
+            &mut TextureAtlasSprite,
+        ),
+        Without<AiShip>,
+    >,
+) {
+    if !keyboard_input.just_pressed(KeyCode::Tab) {
+        return;
+    }
+    for (mut player, mut hull, mut cannons, mut texture_atlas, mut sprite) in
+        player_query.iter_mut()
+    {
+        let class = (player.class + 1) % ships_config.0.len();
+        player.set_class(class, &ships_config.0[class]);
+        hull.set_max_health(ships_config.0[class].hull);
+        cannons.count = ships_config.0[class].cannons;
+        *texture_atlas = handles.ships[class].sprite_sheet.clone();
+        sprite.index = 0;
     }
 }
@@ -0,0 +1,245 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use egui::Visuals;
+
+use crate::{clock::GameClock, loading::GameState};
+
+use super::{
+    ai::AiShip,
+    combat::{Broadside, Cannons},
+    hull::Hull,
+    loader::ShipsConfig,
+    map::Islands,
+    player::{CollisionType, Player, PlayerPositionUpdate},
+    weather::Weather,
+    wind::Wind,
+    ISLAND_SCALING, TILE_SIZE,
+};
+
+pub struct SeaUiPlugin;
+
+impl Plugin for SeaUiPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(hud_fonts_system.system())
+            .on_state_update(GameState::STAGE, GameState::Sea, ui_system.system())
+            .on_state_update(
+                GameState::STAGE,
+                GameState::Sea,
+                land_prompt_system.system(),
+            );
+    }
+}
+
+//the window height the HUD is drawn for at its normal size
+const REFERENCE_HEIGHT: f32 = 720.;
+const MIN_SCALE: f32 = 0.75;
+const MAX_SCALE: f32 = 2.;
+
+//The size of the HUD relative to its normal size, following the height of the window
+fn hud_scale(windows: &Windows) -> f32 {
+    windows
+        .get_primary()
+        .map_or(1., |window| window.height() / REFERENCE_HEIGHT)
+        .max(MIN_SCALE)
+        .min(MAX_SCALE)
+}
+
+//The text follows the scale of the HUD. The fonts are shared by all the UI, so it is scaled too,
+//and they are only rebuilt when the scale changes.
+fn hud_fonts_system(
+    mut egui_context: ResMut<EguiContext>,
+    windows: Res<Windows>,
+    mut last_scale: Local<f32>,
+) {
+    let scale = hud_scale(&windows);
+    if (*last_scale - scale).abs() < 0.01 {
+        return;
+    }
+    *last_scale = scale;
+    let mut fonts = egui::FontDefinitions::default();
+    for (_, size) in fonts.family_and_size.values_mut() {
+        *size *= scale;
+    }
+    egui_context.ctx.set_fonts(fonts);
+}
+
+//The name of the closest island and its distance in tiles, 0 when the ship is over it
+fn closest_island(islands: &Islands, x: i32, y: i32) -> Option<(&str, i32)> {
+    islands
+        .0
+        .iter()
+        .map(|island| {
+            let dx = (island.min_x - x).max(x - island.max_x).max(0);
+            let dy = (island.min_y - y).max(y - island.max_y).max(0);
+            let distance = ((dx * dx + dy * dy) as f32).sqrt().round() as i32;
+            (island.name.as_str(), distance)
+        })
+        .min_by_key(|(_, distance)| *distance)
+}
+
+fn ui_system(
+    mut egui_context: ResMut<EguiContext>,
+    windows: Res<Windows>,
+    clock: Res<GameClock>,
+    weather: Res<Weather>,
+    wind: Res<Wind>,
+    islands: Res<Islands>,
+    pos_update: Res<PlayerPositionUpdate>,
+    ships_config: Res<ShipsConfig>,
+    player_query: Query<(&Player, &Hull, &Cannons), Without<AiShip>>,
+) {
+    let ctx = &mut egui_context.ctx;
+    let scale = hud_scale(&windows);
+    let wind = wind.at(pos_update.translation.truncate());
+    egui::Area::new("sea_hud")
+        .fixed_pos(egui::pos2(4.0 * scale, 4.0 * scale))
+        .show(ctx, |ui| {
+            *ui.visuals_mut() = Visuals::light();
+            ui.style_mut().spacing.item_spacing = ui.style().spacing.item_spacing * scale;
+
+            egui::Frame::group(ui.style())
+                .fill(egui::Color32::WHITE)
+                .show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        wind_indicator(ui, wind, scale);
+                        ui.vertical(|ui| {
+                            ui.label(format!(
+                                "Day {}, {:02}:{:02}",
+                                clock.day,
+                                clock.hour as u32,
+                                (clock.hour.fract() * 60.) as u32
+                            ));
+                            ui.label(format!("Weather : {}", weather.current.kind.name()));
+                            ui.label(format!(
+                                "Wind from {} : {:.0}%",
+                                compass(f32::atan2(-wind.y, -wind.x)),
+                                wind.length() * 100.
+                            ));
+                            for (player, hull, cannons) in player_query.iter() {
+                                let ship_class = &ships_config.0[player.class];
+                                ui.label(format!(
+                                    "{} (cargo : {})",
+                                    ship_class.name, ship_class.cargo_capacity
+                                ));
+                                let heading = player.heading();
+                                ui.label(format!(
+                                    "Heading : {} ({:.0}°)",
+                                    compass(heading),
+                                    //a bearing, clockwise from the north
+                                    (90. - heading.to_degrees()).rem_euclid(360.)
+                                ));
+                                ui.label(format!(
+                                    "Speed : {:.1} tiles/s",
+                                    player.speed() / (TILE_SIZE as f32 * ISLAND_SCALING)
+                                ));
+                                ui.label(format!("Sails : {:.0}%", player.sail * 100.));
+                                ui.label(format!("Trim : {:.0}%", player.trim * 100.));
+                                ui.label(format!(
+                                    "Hull : {:.0}/{:.0}",
+                                    hull.health, hull.max_health
+                                ));
+                                bar(ui, hull.share(), scale);
+                                ui.label(format!("Cannons : {} per side", cannons.count));
+                                ui.horizontal(|ui| {
+                                    ui.label("Port");
+                                    bar(ui, cannons.reload_progress(Broadside::Port), scale);
+                                });
+                                ui.horizontal(|ui| {
+                                    ui.label("Starboard");
+                                    bar(ui, cannons.reload_progress(Broadside::Starboard), scale);
+                                });
+                            }
+                            ui.separator();
+                            ui.label(format!("Position : {}, {}", pos_update.x, pos_update.y));
+                            ui.label(match pos_update.collision_status {
+                                CollisionType::None => "Open sea",
+                                CollisionType::Friction => "In the shallows",
+                                CollisionType::Rigid => "Aground",
+                            });
+                            if let Some((name, distance)) =
+                                closest_island(&islands, pos_update.x, pos_update.y)
+                            {
+                                ui.label(format!("Closest island : {} ({} tiles)", name, distance));
+                            }
+                        });
+                    });
+                });
+        });
+}
+
+//Shown at the bottom of the screen while the ship touches an island
+fn land_prompt_system(
+    mut egui_context: ResMut<EguiContext>,
+    windows: Res<Windows>,
+    islands: Res<Islands>,
+    pos_update: Res<PlayerPositionUpdate>,
+) {
+    let island = match pos_update.island_id {
+        Some(island_id) => &islands.0[island_id as usize],
+        None => return,
+    };
+    let (width, height) = windows
+        .get_primary()
+        .map_or((0., 0.), |window| (window.width(), window.height()));
+    let scale = hud_scale(&windows);
+    egui::Area::new("land_prompt")
+        .fixed_pos(egui::pos2(0., height - 64. * scale))
+        .show(&mut egui_context.ctx, |ui| {
+            *ui.visuals_mut() = Visuals::light();
+            ui.set_min_width(width);
+            ui.vertical_centered(|ui| {
+                egui::Frame::group(ui.style())
+                    .fill(egui::Color32::WHITE)
+                    .show(ui, |ui| {
+                        ui.add(
+                            egui::Label::new(format!("Press Enter to land on {}", island.name))
+                                .text_style(egui::TextStyle::Heading),
+                        );
+                    });
+            });
+        });
+}
+
+//An arrow pointing where the wind blows to, its length is the strength of the wind
+fn wind_indicator(ui: &mut egui::Ui, wind: Vec2, scale: f32) {
+    let size = 48. * scale;
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(size, size), egui::Sense::hover());
+    let painter = ui.painter();
+    let center = rect.center();
+    painter.circle_stroke(
+        center,
+        size / 2. - 1.,
+        egui::Stroke::new(1., egui::Color32::GRAY),
+    );
+    if wind.length_squared() == 0. {
+        return;
+    }
+    let stroke = egui::Stroke::new(2., egui::Color32::BLACK);
+    //egui's y axis points down
+    let tip = egui::vec2(wind.x, -wind.y) * (size / 2. - 4.);
+    let head = tip.normalized() * 6. * scale;
+    painter.line_segment([center - tip, center + tip], stroke);
+    painter.line_segment([center + tip, center + tip - head + head.rot90()], stroke);
+    painter.line_segment([center + tip, center + tip - head - head.rot90()], stroke);
+}
+
+//A horizontal gauge, going from red when empty to green when full
+fn bar(ui: &mut egui::Ui, fraction: f32, scale: f32) {
+    let fraction = fraction.max(0.).min(1.);
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(100., 8.) * scale, egui::Sense::hover());
+    let painter = ui.painter();
+    painter.rect_filled(rect, 2., egui::Color32::LIGHT_GRAY);
+    let mut filled = rect;
+    filled.max.x = rect.min.x + rect.width() * fraction;
+    let color = egui::Color32::from_rgb((255. * (1. - fraction)) as u8, (200. * fraction) as u8, 0);
+    painter.rect_filled(filled, 2., color);
+}
+
+//The compass point of an angle in radians, 0 being east
+pub fn compass(angle: f32) -> &'static str {
+    const POINTS: [&str; 8] = ["E", "NE", "N", "NW", "W", "SW", "S", "SE"];
+    let index = (angle.rem_euclid(2. * PI) / (2. * PI) * 8. + 0.5).floor() as usize % 8;
+    POINTS[index]
+}
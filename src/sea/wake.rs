@@ -0,0 +1,103 @@
+use bevy::prelude::*;
+
+use crate::{
+    loading::GameState,
+    particles::{ParticleBurst, ParticleEmitter, ParticleKind},
+};
+
+use super::{
+    ai::AiShip,
+    hull::{Hull, ShipImpactEvent},
+    player::{CollisionType, Player, PlayerPositionUpdate},
+};
+
+//The water thrown up by the ships: a wake behind them, splashes in the shallows
+//and spray when they hit the rocks
+pub struct SeaWakePlugin;
+impl Plugin for SeaWakePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.on_state_update(
+            GameState::STAGE,
+            GameState::Sea,
+            add_emitter_system.system(),
+        )
+        .on_state_update(GameState::STAGE, GameState::Sea, wake_system.system())
+        .on_state_update(GameState::STAGE, GameState::Sea, spray_system.system());
+    }
+}
+
+//below this speed, the ship leaves no trail
+const MIN_WAKE_SPEED: f32 = 5.;
+//the speed of the thickest wake
+const FULL_WAKE_SPEED: f32 = 100.;
+//particles per second at full speed
+const WAKE_RATE: f32 = 40.;
+const SPLASH_RATE: f32 = 25.;
+//from the center of the ship to its stern, in pixels
+const WAKE_OFFSET: f32 = 30.;
+//the spray of an impact, per pixel per second of speed
+const SPRAY_PER_SPEED: f32 = 0.3;
+const MAX_SPRAY: u32 = 40;
+
+//Every ship, the player's and the AI ones, gets a trail
+fn add_emitter_system(
+    commands: &mut Commands,
+    ship_query: Query<Entity, (With<Player>, Without<ParticleEmitter>)>,
+) {
+    for entity in ship_query.iter() {
+        commands.insert_one(entity, ParticleEmitter::new(ParticleKind::Wake));
+    }
+}
+
+fn wake_system(
+    pos_update: Res<PlayerPositionUpdate>,
+    mut ship_query: Query<(&Player, &Hull, Option<&AiShip>, &mut ParticleEmitter)>,
+) {
+    for (player, hull, ai, mut emitter) in ship_query.iter_mut() {
+        let collision = match ai {
+            Some(ai) => ai.collision,
+            None => pos_update.collision_status,
+        };
+        let speed = player.speed().abs();
+        if hull.health <= 0. || speed < MIN_WAKE_SPEED {
+            emitter.rate = 0.;
+            continue;
+        }
+        let (kind, rate) = match collision {
+            CollisionType::None => (ParticleKind::Wake, WAKE_RATE),
+            CollisionType::Friction => (ParticleKind::Splash, SPLASH_RATE),
+            //stuck against the rocks, the impact sprays instead
+            CollisionType::Rigid => {
+                emitter.rate = 0.;
+                continue;
+            }
+        };
+        //the trail starts behind the ship, from where it goes, rowing backward included
+        let (s, c) = f32::sin_cos(player.heading());
+        let backward = -Vec2::new(c, s) * player.speed().signum();
+        emitter.kind = kind;
+        emitter.rate = rate * (speed / FULL_WAKE_SPEED).min(1.);
+        emitter.offset = backward * WAKE_OFFSET;
+        emitter.direction = backward;
+    }
+}
+
+fn spray_system(
+    mut impact_reader: EventReader<ShipImpactEvent>,
+    mut bursts: ResMut<Events<ParticleBurst>>,
+    ship_query: Query<(&Player, &Transform)>,
+) {
+    for impact in impact_reader.iter() {
+        if let Ok((player, transform)) = ship_query.get(impact.entity) {
+            //the water is thrown back from the bow
+            let (s, c) = f32::sin_cos(player.heading());
+            let forward = Vec2::new(c, s) * impact.speed.signum();
+            bursts.send(ParticleBurst {
+                kind: ParticleKind::Spray,
+                position: transform.translation.truncate() + forward * WAKE_OFFSET,
+                direction: -forward,
+                count: ((impact.speed.abs() * SPRAY_PER_SPEED) as u32).min(MAX_SPRAY),
+            });
+        }
+    }
+}
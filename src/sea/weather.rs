@@ -0,0 +1,343 @@
+use std::{f32::consts::PI, hash::Hasher};
+
+use bevy::{prelude::*, render::camera::Camera};
+use seahash::SeaHasher;
+
+use crate::{
+    background::SeaWater,
+    clock::GameClock,
+    loading::GameState,
+    util::{next_random, SeededHasher},
+};
+
+use super::{player::PlayerPositionUpdate, wind::Wind};
+
+pub struct SeaWeatherPlugin;
+impl Plugin for SeaWeatherPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Weather>()
+            .on_state_enter(
+                GameState::STAGE,
+                GameState::Sea,
+                seed_weather_system.system(),
+            )
+            .on_state_enter(
+                GameState::STAGE,
+                GameState::Sea,
+                spawn_overlay_system.system(),
+            )
+            .on_state_update(GameState::STAGE, GameState::Sea, weather_system.system())
+            .on_state_update(GameState::STAGE, GameState::Sea, gust_system.system())
+            .on_state_update(GameState::STAGE, GameState::Sea, overlay_system.system())
+            .on_state_exit(
+                GameState::STAGE,
+                GameState::Sea,
+                exit_weather_system.system(),
+            );
+    }
+}
+
+//the side of a weather region, in pixels
+const REGION_SIZE: f32 = 8000.;
+//the in-game hours a weather lasts in a region
+const PERIOD: f32 = 4.;
+//the weather turns into the next one during the end of the period, in hours
+const TRANSITION: f32 = 1.;
+//the gusts of a full storm, in pixels per second
+const GUST_SPEED: f32 = 60.;
+const GUST_DURATION: f32 = 1.5;
+//the seconds between two gusts in a full storm, more when the gusts are weaker
+const MIN_GUST_INTERVAL: f32 = 2.;
+const MAX_GUST_INTERVAL: f32 = 7.;
+//the gusts blow up to this angle away from the wind
+const GUST_ANGLE: f32 = PI / 6.;
+//over the ships and the islands, under the camera
+const OVERLAY_Z: f32 = 500.;
+const OVERLAY_COLOR: (f32, f32, f32) = (0.45, 0.5, 0.55);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WeatherKind {
+    Clear,
+    Overcast,
+    Rain,
+    Storm,
+}
+impl WeatherKind {
+    //the kinds and their chances
+    const WEIGHTS: [(WeatherKind, u64); 4] = [
+        (WeatherKind::Clear, 50),
+        (WeatherKind::Overcast, 25),
+        (WeatherKind::Rain, 15),
+        (WeatherKind::Storm, 10),
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            WeatherKind::Clear => "Clear",
+            WeatherKind::Overcast => "Overcast",
+            WeatherKind::Rain => "Rain",
+            WeatherKind::Storm => "Storm",
+        }
+    }
+
+    fn conditions(self) -> Conditions {
+        let (wind, gusts, darkness, fog, clouds) = match self {
+            WeatherKind::Clear => (1., 0., 0., 0., 0.25),
+            WeatherKind::Overcast => (1.1, 0., 0.15, 0.1, 0.7),
+            WeatherKind::Rain => (1.25, 0.3, 0.3, 0.35, 0.85),
+            WeatherKind::Storm => (1.7, 1., 0.55, 0.6, 1.),
+        };
+        Conditions {
+            kind: self,
+            wind,
+            gusts,
+            darkness,
+            fog,
+            clouds,
+        }
+    }
+}
+
+//What the weather does at a place, blended between the neighbouring regions and periods
+#[derive(Clone, Copy, Debug)]
+pub struct Conditions {
+    //the weather weighing the most
+    pub kind: WeatherKind,
+    //multiplies the strength of the wind
+    pub wind: f32,
+    //from 0, no gusts, to 1, the gusts of a full storm
+    pub gusts: f32,
+    //how much the sea is darkened, from 0 to 1
+    pub darkness: f32,
+    //how much the view is hidden, from 0 to 1
+    pub fog: f32,
+    //how much of the sky is covered, from 0 to 1
+    pub clouds: f32,
+}
+impl Default for Conditions {
+    fn default() -> Self {
+        WeatherKind::Clear.conditions()
+    }
+}
+
+//The weather over the sea. It is drawn from the seed for each region and period of the clock,
+//so the same save always sees the same storms.
+pub struct Weather {
+    hasher: SeaHasher,
+    //at the player's position
+    pub current: Conditions,
+    gust: Vec2,
+    //the time left in the current gust, then before the next one, in seconds
+    gust_time: f32,
+    next_gust: f32,
+    random_counter: u64,
+}
+impl Default for Weather {
+    fn default() -> Self {
+        Weather {
+            hasher: SeaHasher::new(),
+            current: Conditions::default(),
+            gust: Vec2::zero(),
+            gust_time: 0.,
+            next_gust: MAX_GUST_INTERVAL,
+            random_counter: 0,
+        }
+    }
+}
+impl Weather {
+    fn kind(&self, region: (i32, i32), period: i64) -> WeatherKind {
+        let mut hasher = self.hasher;
+        hasher.write_i32(region.0);
+        hasher.write_i32(region.1);
+        hasher.write_i64(period);
+        let total: u64 = WeatherKind::WEIGHTS.iter().map(|(_, weight)| weight).sum();
+        let mut roll = hasher.finish() % total;
+        for (kind, weight) in WeatherKind::WEIGHTS.iter() {
+            if roll < *weight {
+                return *kind;
+            }
+            roll -= weight;
+        }
+        WeatherKind::Clear
+    }
+
+    //The weather at a world position and time
+    pub fn at(&self, position: Vec2, clock: &GameClock) -> Conditions {
+        let hours = (clock.day - 1) as f32 * 24. + clock.hour;
+        let period = (hours / PERIOD).floor();
+        let into_period = hours - period * PERIOD;
+        let next = smoothstep((into_period - (PERIOD - TRANSITION)) / TRANSITION);
+        //the weathers are at the center of their regions, and blend in between
+        let region = position / REGION_SIZE - Vec2::new(0.5, 0.5);
+        let corner = region.floor();
+        let (fx, fy) = (
+            smoothstep(region.x - corner.x),
+            smoothstep(region.y - corner.y),
+        );
+
+        let mut blend = Conditions {
+            kind: WeatherKind::Clear,
+            wind: 0.,
+            gusts: 0.,
+            darkness: 0.,
+            fog: 0.,
+            clouds: 0.,
+        };
+        let mut weights = [0.; 4];
+        for (i, j, region_weight) in [
+            (0, 0, (1. - fx) * (1. - fy)),
+            (1, 0, fx * (1. - fy)),
+            (0, 1, (1. - fx) * fy),
+            (1, 1, fx * fy),
+        ]
+        .iter()
+        {
+            let region = (corner.x as i32 + i, corner.y as i32 + j);
+            for (offset, period_weight) in [(0, 1. - next), (1, next)].iter() {
+                let kind = self.kind(region, period as i64 + offset);
+                let weight = region_weight * period_weight;
+                let conditions = kind.conditions();
+                blend.wind += conditions.wind * weight;
+                blend.gusts += conditions.gusts * weight;
+                blend.darkness += conditions.darkness * weight;
+                blend.fog += conditions.fog * weight;
+                blend.clouds += conditions.clouds * weight;
+                weights[kind as usize] += weight;
+            }
+        }
+        blend.kind = WeatherKind::WEIGHTS
+            .iter()
+            .map(|(kind, _)| *kind)
+            .max_by(|a, b| {
+                weights[*a as usize]
+                    .partial_cmp(&weights[*b as usize])
+                    .unwrap()
+            })
+            .unwrap_or(WeatherKind::Clear);
+        blend
+    }
+
+    //The push of the current gust on a ship, in pixels per second
+    pub fn gust(&self) -> Vec2 {
+        if self.gust_time <= 0. {
+            return Vec2::zero();
+        }
+        //it rises and falls
+        self.gust * f32::sin(PI * self.gust_time / GUST_DURATION)
+    }
+}
+
+fn smoothstep(x: f32) -> f32 {
+    let x = x.max(0.).min(1.);
+    x * x * (3. - 2. * x)
+}
+
+//The screen overlay hiding the view in bad weather
+struct WeatherOverlay;
+
+fn seed_weather_system(mut weather: ResMut<Weather>, hasher: Res<SeededHasher>) {
+    let mut hasher: SeaHasher = hasher.get_hasher();
+    hasher.write(b"sea_weather");
+    weather.hasher = hasher;
+}
+
+fn weather_system(
+    clock: Res<GameClock>,
+    pos_update: Res<PlayerPositionUpdate>,
+    mut weather: ResMut<Weather>,
+    mut wind: ResMut<Wind>,
+    mut water_query: Query<&mut SeaWater>,
+) {
+    weather.current = weather.at(pos_update.translation.truncate(), &clock);
+    wind.strength_factor = weather.current.wind;
+    for mut water in water_query.iter_mut() {
+        water.darkness = weather.current.darkness;
+    }
+}
+
+//Bad weather blows gusts from around the wind direction, at random times
+fn gust_system(
+    time: Res<Time>,
+    pos_update: Res<PlayerPositionUpdate>,
+    wind: Res<Wind>,
+    mut weather: ResMut<Weather>,
+) {
+    let delta = time.delta_seconds();
+    if weather.gust_time > 0. {
+        weather.gust_time -= delta;
+        return;
+    }
+    let gusts = weather.current.gusts;
+    if gusts <= 0. {
+        return;
+    }
+    weather.next_gust -= delta;
+    if weather.next_gust > 0. {
+        return;
+    }
+    let mut counter = weather.random_counter;
+    let wind = wind.at(pos_update.translation.truncate());
+    let angle = f32::atan2(wind.y, wind.x) + (2. * next_random(&mut counter) - 1.) * GUST_ANGLE;
+    let strength = GUST_SPEED * gusts * (0.5 + 0.5 * next_random(&mut counter));
+    weather.gust = Vec2::new(angle.cos(), angle.sin()) * strength;
+    weather.gust_time = GUST_DURATION;
+    weather.next_gust = (MIN_GUST_INTERVAL
+        + (MAX_GUST_INTERVAL - MIN_GUST_INTERVAL) * next_random(&mut counter))
+        / gusts;
+    weather.random_counter = counter;
+}
+
+fn spawn_overlay_system(commands: &mut Commands, mut materials: ResMut<Assets<ColorMaterial>>) {
+    let (r, g, b) = OVERLAY_COLOR;
+    commands
+        .spawn(SpriteBundle {
+            material: materials.add(Color::rgba(r, g, b, 0.).into()),
+            transform: Transform::from_translation(Vec3::new(0., 0., OVERLAY_Z)),
+            ..Default::default()
+        })
+        .with(WeatherOverlay);
+}
+
+//The overlay covers the screen, more opaque as the fog thickens
+fn overlay_system(
+    windows: Res<Windows>,
+    weather: Res<Weather>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    camera_query: Query<&Transform, With<Camera>>,
+    mut overlay_query: Query<
+        (&mut Transform, &mut Sprite, &Handle<ColorMaterial>),
+        With<WeatherOverlay>,
+    >,
+) {
+    let size = windows.get_primary().map_or(Vec2::zero(), |window| {
+        Vec2::new(window.width(), window.height())
+    });
+    for (mut transform, mut sprite, material) in overlay_query.iter_mut() {
+        for camera_transform in camera_query.iter() {
+            transform.translation.x = camera_transform.translation.x;
+            transform.translation.y = camera_transform.translation.y;
+            transform.scale = camera_transform.scale;
+        }
+        sprite.size = size;
+        if let Some(material) = materials.get_mut(material) {
+            //a storm is darker than a rain
+            let shade = 1. - weather.current.darkness * 0.5;
+            let (r, g, b) = OVERLAY_COLOR;
+            material.color =
+                Color::rgba(r * shade, g * shade, b * shade, weather.current.fog * 0.7);
+        }
+    }
+}
+
+fn exit_weather_system(
+    commands: &mut Commands,
+    mut weather: ResMut<Weather>,
+    mut wind: ResMut<Wind>,
+    overlay_query: Query<Entity, With<WeatherOverlay>>,
+) {
+    for entity in overlay_query.iter() {
+        commands.despawn(entity);
+    }
+    weather.gust_time = 0.;
+    wind.strength_factor = 1.;
+}
@@ -0,0 +1,139 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use noise::{NoiseFn, Perlin, Seedable};
+use seahash::SeaHasher;
+use std::hash::Hasher;
+
+use crate::{loading::GameState, util::SeededHasher};
+
+pub struct SeaWindPlugin;
+impl Plugin for SeaWindPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Wind>()
+            .on_state_enter(GameState::STAGE, GameState::Sea, seed_wind_system.system())
+            .on_state_update(GameState::STAGE, GameState::Sea, wind_system.system());
+    }
+}
+
+//how fast the global wind turns and changes strength
+const DIRECTION_CHANGE_RATE: f64 = 0.002;
+const STRENGTH_CHANGE_RATE: f64 = 0.005;
+const MIN_STRENGTH: f32 = 0.2;
+const MAX_STRENGTH: f32 = 1.;
+//the size, in pixels, of the local variations of the wind
+const LOCAL_SCALE: f64 = 3000.;
+const LOCAL_CHANGE_RATE: f64 = 0.01;
+const LOCAL_ANGLE_VARIATION: f32 = PI / 6.;
+const LOCAL_STRENGTH_VARIATION: f32 = 0.25;
+
+//The wind blowing over the sea. It slowly changes over time, and varies a bit from place to place.
+//Both are sampled from a noise seeded with the world seed.
+pub struct Wind {
+    noise: Perlin,
+    time: f64,
+    //set by the weather, storms blow stronger than the usual maximum
+    pub strength_factor: f32,
+}
+impl Default for Wind {
+    fn default() -> Self {
+        Wind {
+            noise: Perlin::new(),
+            time: 0.,
+            strength_factor: 1.,
+        }
+    }
+}
+impl Wind {
+    //The wind at a world position, pointing where it blows to. Its length is the strength, between 0 and 1 out of storms.
+    pub fn at(&self, pos: Vec2) -> Vec2 {
+        let (angle, strength) = self.global();
+        let x = pos.x as f64 / LOCAL_SCALE;
+        let y = pos.y as f64 / LOCAL_SCALE;
+        let t = self.time * LOCAL_CHANGE_RATE;
+        let angle = angle + self.noise.get([x, y, t]) as f32 * LOCAL_ANGLE_VARIATION;
+        let strength = strength
+            * (1. + self.noise.get([x + 0.5, y + 0.5, t + 100.]) as f32 * LOCAL_STRENGTH_VARIATION);
+        Vec2::new(angle.cos(), angle.sin())
+            * strength.max(0.).min(MAX_STRENGTH)
+            * self.strength_factor
+    }
+
+    //The wind direction (an angle, where it blows to) and strength, ignoring the local variations
+    pub fn global(&self) -> (f32, f32) {
+        let angle = self.noise.get([self.time * DIRECTION_CHANGE_RATE, 0.5]) as f32 * 2. * PI;
+        let strength = MIN_STRENGTH
+            + (MAX_STRENGTH - MIN_STRENGTH)
+                * (0.5 + 0.5 * self.noise.get([0.5, self.time * STRENGTH_CHANGE_RATE]) as f32);
+        (angle, strength)
+    }
+}
+
+fn seed_wind_system(mut wind: ResMut<Wind>, hasher: Res<SeededHasher>) {
+    let mut hasher: SeaHasher = hasher.get_hasher();
+    hasher.write(b"sea_wind");
+    wind.noise = Perlin::new().set_seed(hasher.finish() as u32);
+}
+
+fn wind_system(time: Res<Time>, mut wind: ResMut<Wind>) {
+    wind.time += time.delta_seconds_f64();
+}
+
+//The share of the sail power a ship gets depending on the angle between its heading and where the wind comes from, in degrees.
+//A ship can't sail into the wind, is at its fastest on a beam reach and a bit slower when running with the wind.
+const POLAR: [(f32, f32); 7] = [
+    (0., 0.),
+    (40., 0.),
+    (55., 0.6),
+    (90., 0.95),
+    (110., 1.),
+    (150., 0.85),
+    (180., 0.7),
+];
+
+fn polar(off_wind: f32) -> f32 {
+    let off_wind = off_wind.to_degrees();
+    for window in POLAR.windows(2) {
+        let ((a0, s0), (a1, s1)) = (window[0], window[1]);
+        if off_wind <= a1 {
+            return s0 + (s1 - s0) * (off_wind - a0) / (a1 - a0);
+        }
+    }
+    POLAR[POLAR.len() - 1].1
+}
+
+//How much of the wind a sail trimmed at `trim` (0 is sheeted in, 1 is fully let out) catches.
+//The best trim goes from sheeted in when close-hauled to let out when running.
+fn trim_efficiency(off_wind: f32, trim: f32) -> f32 {
+    let error = (trim - best_trim_off_wind(off_wind)) * PI / 2.;
+    error.cos().powi(2)
+}
+
+fn best_trim_off_wind(off_wind: f32) -> f32 {
+    ((off_wind.to_degrees() - 40.) / 140.).max(0.).min(1.)
+}
+
+//the angle between the heading and where the wind comes from, between 0 and PI
+fn off_wind(heading: f32, wind: Vec2) -> f32 {
+    let from_wind = f32::atan2(-wind.y, -wind.x);
+    let off_wind = (heading - from_wind).abs() % (2. * PI);
+    if off_wind > PI {
+        2. * PI - off_wind
+    } else {
+        off_wind
+    }
+}
+
+//The point of sail model : the share of the sail power a ship heading at `heading` gets from the wind.
+pub fn sail_efficiency(heading: f32, wind: Vec2, trim: f32) -> f32 {
+    if wind.length_squared() == 0. {
+        return 0.;
+    }
+    let off_wind = off_wind(heading, wind);
+    wind.length() * polar(off_wind) * trim_efficiency(off_wind, trim)
+}
+
+//The trim catching the most wind for a ship heading at `heading`
+pub fn best_trim(heading: f32, wind: Vec2) -> f32 {
+    best_trim_off_wind(off_wind(heading, wind))
+}
@@ -1,9 +1,13 @@
-use crate::{land::mobs::Mob, loading::GameState, util::SeededHasher};
+use crate::{
+    background::{WaterPalette, SHORE_ATTRIBUTE},
+    land::mobs::Mob,
+    lighting::LightKind,
+    loading::GameState,
+    util::SeededHasher,
+};
 
 use super::{
-    loader::BiomeConfig,
-    map::TileKind::*,
-    player::{CollisionType, PlayerPositionUpdate},
+    collision::StreamingArea, loader::BiomeConfig, map::TileKind::*, player::CollisionType,
     TILE_SIZE,
 };
 use super::{loader::SeaHandles, map::TileKind};
@@ -115,6 +119,8 @@ pub struct Biome {
     pub name: String,
     pub sea_sheet: String,
     pub land_sheet: String,
+    #[serde(default)]
+    pub water: WaterPalette,
     pub weight: u32,
 }
 //The world generator only decide which tile type must be used at each coordinate (among sand, sea and forest here)
@@ -298,7 +304,6 @@ pub fn select_biome(
     config[0].clone()
 }
 
-const VIEW_DISTANCE: i32 = 50;
 #[derive(Default, Debug)]
 struct Ribbon {
     neg: Vec<(i32, i32)>,
@@ -351,7 +356,7 @@ impl Ribbon {
         self.neg.push((y_value, y_value + 1))
     }
 }
-struct GenRessources {
+pub(crate) struct GenRessources {
     pub noise: Fbm,
     pub hasher: SeaHasher,
     pub biome: Biome,
@@ -378,6 +383,13 @@ impl FromResources for GenRessources {
     }
 }
 
+impl GenRessources {
+    //Whether a tile is below the sea level, even if its island isn't generated yet
+    pub fn is_sea(&self, position: (i32, i32)) -> bool {
+        get_height(&self.noise, position) < self.biome.generation_parameters.sea_level as f64
+    }
+}
+
 fn get_height(noise: &Fbm, (x, y): (i32, i32)) -> f64 {
     noise.get([x as f64, y as f64])
 }
@@ -392,7 +404,7 @@ pub struct IslandQueue(pub Vec<Island>);
 fn worldgen_system(
     mut island_map: Local<HashSet<IslandPos>>,
     mut islands_to_add: ResMut<IslandQueue>,
-    player_pos: Res<PlayerPositionUpdate>,
+    area: Res<StreamingArea>,
     mut ribbon: Local<Ribbon>,
     gen_ressources: Local<GenRessources>,
     mut meshes: ResMut<Assets<Mesh>>,
@@ -400,35 +412,38 @@ fn worldgen_system(
     handles: Res<SeaHandles>,
 ) {
     let tile_size = Vec2::new(TILE_SIZE as f32, TILE_SIZE as f32);
+    let view_distance = area.generation_distance;
+    //around the ship, or the free camera
+    let (center_x, center_y) = area.center;
 
-    if ribbon.len_pos() - player_pos.x <= VIEW_DISTANCE {
-        ribbon.expand_pos(player_pos.y)
+    if ribbon.len_pos() - center_x <= view_distance {
+        ribbon.expand_pos(center_y)
     }
-    if player_pos.x - ribbon.len_neg() <= VIEW_DISTANCE {
-        ribbon.expand_neg(player_pos.y)
+    if center_x - ribbon.len_neg() <= view_distance {
+        ribbon.expand_neg(center_y)
     }
     let mut island_tiles = VecDeque::new();
     for (i, (min, max)) in ribbon.iter_mut_enumerate() {
         //if to far off horizontally, skips.
-        if (i - player_pos.x).abs() > VIEW_DISTANCE {
+        if (i - center_x).abs() > view_distance {
             continue;
         }
         //if the player did a large circle for example, the ribbon can be very far.
         //this discards the far segment and makes a new one closer.
         //it just discards a bit of cache, but the generated islands are kept, no no big deal.
-        if *min - player_pos.y >= 2 * VIEW_DISTANCE || *max - player_pos.y >= 2 * VIEW_DISTANCE {
-            *min = player_pos.y;
-            *max = player_pos.y + 1;
+        if *min - center_y >= 2 * view_distance || *max - center_y >= 2 * view_distance {
+            *min = center_y;
+            *max = center_y + 1;
         }
         //finally, enlarges the ribbon when necessary
-        if player_pos.y - *min <= VIEW_DISTANCE {
+        if center_y - *min <= view_distance {
             let height = get_height(&gen_ressources.noise, (i, *min));
             if height >= gen_ressources.biome.generation_parameters.sea_level as f64 {
                 island_tiles.push_back((i, *min))
             }
             *min -= 1;
         }
-        if *max - player_pos.y <= VIEW_DISTANCE {
+        if *max - center_y <= view_distance {
             let height = get_height(&gen_ressources.noise, (i, *max));
             if height >= gen_ressources.biome.generation_parameters.sea_level as f64 {
                 island_tiles.push_back((i, *max))
@@ -451,6 +466,7 @@ fn worldgen_system(
             atlases.get(handles.islands_sheet.clone()).unwrap(),
             &mut *meshes,
             tile_size,
+            view_distance,
         ) {
             islands_to_add.0.push(island)
         };
@@ -483,15 +499,168 @@ impl Tile {
 pub struct Island {
     pub tiles: Arc<Vec<Vec<Tile>>>,
     pub mesh: Handle<Mesh>,
+    //the shallow water and foam around the coasts
+    pub shore_mesh: Handle<Mesh>,
     pub min_x: i32,
     pub max_x: i32,
     pub min_y: i32,
     pub max_y: i32,
+    pub name: String,
+    //the light sources, at positions in the island mesh
+    pub lights: Vec<(LightKind, Vec2)>,
     pub entity: Option<Entity>,
     pub rigid_trimesh: Option<TriMesh>,
     pub friction_trimesh: Option<TriMesh>,
     pub mobs: Vec<(Mob, Transform)>,
 }
+//the islands with this many land tiles have a lighthouse, and those with half of it a port
+const LIGHTHOUSE_TILES: usize = 60;
+//the chance for an inland sand tile to have a campfire
+const CAMPFIRE_CHANCE: u64 = 80;
+const MAX_CAMPFIRES: usize = 4;
+
+//Places the lights of an island : a lighthouse and a port on opposite coasts, and a few campfires
+fn place_lights(
+    tiles_vec: &[Vec<Tile>],
+    mut hasher: SeaHasher,
+    position: (i32, i32),
+    tile_size: Vec2,
+) -> Vec<(LightKind, Vec2)> {
+    hasher.write(b"island_lights");
+    hasher.write_i32(position.0);
+    hasher.write_i32(position.1);
+    let kind_at = |x: i32, y: i32| {
+        if x < 0 || y < 0 {
+            return Sea(false);
+        }
+        tiles_vec
+            .get(x as usize)
+            .map(|column| column.get(y as usize))
+            .flatten()
+            .map_or(Sea(false), |tile| tile.kind)
+    };
+    let mut land_tiles = 0;
+    let mut coast = Vec::new();
+    let mut inland_sand = Vec::new();
+    for (x, column) in tiles_vec.iter().enumerate() {
+        for (y, tile) in column.iter().enumerate() {
+            if let Sea(_) = tile.kind {
+                continue;
+            }
+            land_tiles += 1;
+            let (x, y) = (x as i32, y as i32);
+            let on_coast = [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)]
+                .iter()
+                .any(|(i, j)| kind_at(*i, *j) == Sea(false));
+            if on_coast {
+                coast.push((x, y));
+            } else if tile.kind == Sand(false) {
+                inland_sand.push((x, y));
+            }
+        }
+    }
+    let center =
+        |(x, y): (i32, i32)| (Vec2::new(x as f32, y as f32) + Vec2::new(0.5, 0.5)) * tile_size;
+    let mut lights = Vec::new();
+    if !coast.is_empty() && land_tiles >= LIGHTHOUSE_TILES / 2 {
+        let lighthouse = (hasher.finish() % coast.len() as u64) as usize;
+        if land_tiles >= LIGHTHOUSE_TILES {
+            lights.push((LightKind::Lighthouse, center(coast[lighthouse])));
+        }
+        //the coast tiles are in column order, half of them away is another side
+        let port = (lighthouse + coast.len() / 2) % coast.len();
+        lights.push((LightKind::PortLight, center(coast[port])));
+    }
+    let mut campfires = 0;
+    for tile in inland_sand {
+        let mut tile_hasher = hasher;
+        tile_hasher.write_i32(tile.0);
+        tile_hasher.write_i32(tile.1);
+        if tile_hasher.finish() % CAMPFIRE_CHANCE == 0 {
+            lights.push((LightKind::Campfire, center(tile)));
+            campfires += 1;
+            if campfires >= MAX_CAMPFIRES {
+                break;
+            }
+        }
+    }
+    lights
+}
+
+//The width of the shallow water around the islands, in tiles
+pub const SHORE_WIDTH: i32 = 3;
+
+//A quad for every sea tile close to the land, its vertices giving their distance to the coast in tiles.
+//The band goes past the edges of the island grid, since those are coasts too.
+fn build_shore_mesh(tiles_vec: &[Vec<Tile>], tile_size: Vec2) -> Mesh {
+    let is_land = |x: i32, y: i32| {
+        x >= 0
+            && y >= 0
+            && tiles_vec
+                .get(x as usize)
+                .map(|column| column.get(y as usize))
+                .flatten()
+                .map_or(false, |tile| tile.kind != Sea(false))
+    };
+    let size_x = tiles_vec.len() as i32;
+    let size_y = tiles_vec.first().map_or(0, |column| column.len()) as i32;
+    //the distance from the center of a tile to the closest land tile, capped past the band
+    let distance = |x: i32, y: i32| {
+        let mut closest = (SHORE_WIDTH + 1) as f32;
+        for i in -SHORE_WIDTH..=SHORE_WIDTH {
+            for j in -SHORE_WIDTH..=SHORE_WIDTH {
+                if is_land(x + i, y + j) {
+                    closest = closest.min(((i * i + j * j) as f32).sqrt());
+                }
+            }
+        }
+        closest
+    };
+    let mut positions = Vec::new();
+    let mut shore = Vec::new();
+    let mut indices = Vec::new();
+    for x in -SHORE_WIDTH..size_x + SHORE_WIDTH {
+        for y in -SHORE_WIDTH..size_y + SHORE_WIDTH {
+            if is_land(x, y) || distance(x, y) > SHORE_WIDTH as f32 {
+                continue;
+            }
+            let first = positions.len() as u32;
+            //each corner takes the mean of the tiles around it, so the band is smooth
+            for (corner_x, corner_y) in [(x, y), (x + 1, y), (x + 1, y + 1), (x, y + 1)].iter() {
+                let (corner_x, corner_y) = (*corner_x, *corner_y);
+                positions.push([
+                    corner_x as f32 * tile_size.x,
+                    corner_y as f32 * tile_size.y,
+                    0.,
+                ]);
+                let around = [
+                    (corner_x - 1, corner_y - 1),
+                    (corner_x, corner_y - 1),
+                    (corner_x - 1, corner_y),
+                    (corner_x, corner_y),
+                ];
+                let sum: f32 = around
+                    .iter()
+                    .map(|(i, j)| {
+                        if is_land(*i, *j) {
+                            0.
+                        } else {
+                            distance(*i, *j)
+                        }
+                    })
+                    .sum();
+                shore.push(sum / 4.);
+            }
+            indices.extend_from_slice(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+        }
+    }
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.set_attribute(SHORE_ATTRIBUTE, shore);
+    mesh.set_indices(Some(bevy::render::mesh::Indices::U32(indices)));
+    mesh
+}
+
 fn get_surroundings(tiles_vec: &[Vec<Tile>], i: usize, j: usize) -> [TileKind; 9] {
     [
         tiles_vec
@@ -645,6 +814,30 @@ fn add_tile_to_trimesh(
     indices.push([j, j + 3, j + 2]);
     *i += 4;
 }
+//A name made of syllables, the same for an island in every session with the same seed
+fn island_name(mut hasher: SeaHasher, position: (i32, i32)) -> String {
+    const SYLLABLES: [&str; 16] = [
+        "ba", "ko", "ri", "ma", "tu", "len", "sa", "vo", "nar", "el", "do", "fi", "ta", "mor",
+        "ish", "ga",
+    ];
+    hasher.write(b"island_name");
+    hasher.write_i32(position.0);
+    hasher.write_i32(position.1);
+    let mut hash = hasher.finish();
+    let count = 2 + hash % 2;
+    hash /= 2;
+    let mut name = String::new();
+    for _ in 0..count {
+        name.push_str(SYLLABLES[(hash % SYLLABLES.len() as u64) as usize]);
+        hash /= SYLLABLES.len() as u64;
+    }
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => name,
+    }
+}
+
 fn generate_island(
     tile: (i32, i32),
     gen_ressources: &GenRessources,
@@ -655,6 +848,7 @@ fn generate_island(
     atlas: &TextureAtlas,
     meshes: &mut Assets<Mesh>,
     tile_size: Vec2,
+    view_distance: i32,
 ) -> Option<Island> {
     let mut min_x = tile.0;
     let mut max_x = tile.0;
@@ -697,7 +891,7 @@ fn generate_island(
             }
             let (min, max) = &mut ribbon[nx];
             //if there is a gap that is too large, move the ribbon
-            if *min - ny >= 2 * VIEW_DISTANCE || ny - *max >= 2 * VIEW_DISTANCE {
+            if *min - ny >= 2 * view_distance || ny - *max >= 2 * view_distance {
                 *min = ny - 1;
                 *max = ny + 1;
             }
@@ -775,6 +969,8 @@ fn generate_island(
             }
         }
     }
+    let shore_mesh = build_shore_mesh(&tiles_vec, tile_size);
+    let lights = place_lights(&tiles_vec, gen_ressources.hasher, (min_x, min_y), tile_size);
     let mut rigid_positions = Vec::new(); //everything that must be constructed
     let mut rigid_indices = Vec::new();
     let mut rigid_i = 0;
@@ -850,8 +1046,11 @@ fn generate_island(
         max_x,
         min_y,
         max_y,
+        name: island_name(gen_ressources.hasher, (min_x, min_y)),
+        lights,
         tiles: Arc::new(tiles_vec),
         mesh: meshes.add(mesh),
+        shore_mesh: meshes.add(shore_mesh),
         entity: None,
         rigid_trimesh,
         friction_trimesh,
@@ -1,11 +1,11 @@
-use std::hash::Hash;
+use std::hash::{Hash, Hasher};
 
 use bevy::{
     prelude::{Texture, TextureAtlas},
     render::texture::TextureFormat,
 };
 use density_mesh_core::prelude::{DensityMap, DensityMeshGenerator, GenerateDensityMeshSettings};
-use image::Pixel;
+use image::{imageops, GrayImage, Pixel};
 use parry2d::{math::Point, shape::TriMesh};
 use seahash::SeaHasher;
 
@@ -26,6 +26,14 @@ impl SeededHasher {
     }
 }
 
+//a cheap deterministic random number between 0 and 1
+pub fn next_random(counter: &mut u64) -> f32 {
+    *counter += 1;
+    let mut hasher = SeaHasher::new();
+    hasher.write_u64(*counter);
+    (hasher.finish() % 10000) as f32 / 10000.
+}
+
 pub(crate) fn texture_to_image(texture: &Texture) -> Option<image::DynamicImage> {
     match texture.format {
         TextureFormat::R8Unorm => image::ImageBuffer::from_raw(
@@ -55,34 +63,35 @@ pub(crate) fn texture_to_image(texture: &Texture) -> Option<image::DynamicImage>
         _ => None,
     }
 }
-pub fn texture_atlas_to_trimeshes(
-    atlas: &TextureAtlas,
-    texture: &Texture,
-    scale: f32,
-) -> Vec<TriMesh> {
+//The settings used to turn the collision sheets into meshes. They are part of the hull cache key.
+pub fn hull_mesh_settings() -> GenerateDensityMeshSettings {
+    GenerateDensityMeshSettings {
+        points_separation: 4.0.into(),
+        keep_invisible_triangles: false,
+        ..Default::default()
+    }
+}
+
+//Turns every sprite of a collision atlas into a trimesh, centered on the sprite and scaled by scale.
+//Works on an already decoded image so it can also be used outside of bevy.
+pub fn image_to_trimeshes(image: &GrayImage, atlas: &TextureAtlas, scale: f32) -> Vec<TriMesh> {
     let mut meshes = Vec::new();
     for rect in &atlas.textures {
         let size = rect.max - rect.min;
-        let image = texture_to_image(texture)
-            .unwrap()
-            .crop(
-                rect.min.x as u32,
-                rect.min.y as u32,
-                size.x as u32,
-                size.y as u32,
-            )
-            .into_luma8()
-            .pixels()
-            .map(|p| p.channels()[0])
-            .collect();
+        let image = imageops::crop_imm(
+            image,
+            rect.min.x as u32,
+            rect.min.y as u32,
+            size.x as u32,
+            size.y as u32,
+        )
+        .to_image()
+        .pixels()
+        .map(|p| p.channels()[0])
+        .collect();
         let map = DensityMap::new(size.x as usize, size.y as usize, 1, image).unwrap();
 
-        let settings = GenerateDensityMeshSettings {
-            points_separation: 4.0.into(),
-            keep_invisible_triangles: false,
-            ..Default::default()
-        };
-        let mut generator = DensityMeshGenerator::new(vec![], map, settings.clone());
+        let mut generator = DensityMeshGenerator::new(vec![], map, hull_mesh_settings());
         generator.process_wait().expect("Failed to process image");
         let mut mesh = generator.into_mesh().unwrap();
         let vertices = mesh